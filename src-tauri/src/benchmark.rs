@@ -0,0 +1,108 @@
+use std::time::Duration;
+
+use anyhow::{Result, bail};
+use serde::{Deserialize, Serialize};
+
+use crate::database::{DbActions, Operation};
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct BenchmarkStats {
+    pub min: Duration,
+    pub max: Duration,
+    pub mean: Duration,
+    pub median: Duration,
+    pub p95: Duration,
+    pub p99: Duration,
+    pub stddev: Duration,
+    // In the order the runs were made
+    pub samples: Vec<Duration>,
+}
+
+impl BenchmarkStats {
+    pub fn from_samples(samples: Vec<Duration>) -> Result<Self> {
+        if samples.is_empty() {
+            bail!("Cannot compute statistics without samples");
+        }
+
+        let mut sorted = samples.clone();
+        sorted.sort();
+
+        let count = samples.len() as f64;
+        let mean_nanos = samples.iter().map(|d| d.as_nanos() as f64).sum::<f64>() / count;
+        let variance = samples.iter()
+            .map(|d| (d.as_nanos() as f64 - mean_nanos).powi(2))
+            .sum::<f64>() / count;
+
+        Ok(Self {
+            min: sorted[0],
+            max: sorted[sorted.len() - 1],
+            mean: Duration::from_nanos(mean_nanos.round() as u64),
+            median: percentile(&sorted, 50.0),
+            p95: percentile(&sorted, 95.0),
+            p99: percentile(&sorted, 99.0),
+            stddev: Duration::from_nanos(variance.sqrt().round() as u64),
+            samples,
+        })
+    }
+}
+
+// Nearest-rank percentile of already sorted samples
+fn percentile(sorted: &[Duration], percent: f64) -> Duration {
+    let rank = (percent / 100.0 * sorted.len() as f64).ceil() as usize;
+    sorted[rank.clamp(1, sorted.len()) - 1]
+}
+
+/// Runs the operation `warmup` times without measuring, then `iterations` times collecting the reported durations.
+pub async fn run_benchmark(db: &mut (dyn DbActions + Send), operation: &Operation, iterations: u32, warmup: u32) -> Result<BenchmarkStats> {
+    if iterations == 0 {
+        bail!("Number of iterations must be greater than zero");
+    }
+
+    for _ in 0..warmup {
+        operation.run(db).await?;
+    }
+
+    let mut samples = Vec::with_capacity(iterations as usize);
+    for _ in 0..iterations {
+        samples.push(operation.run(db).await?.duration);
+    }
+
+    BenchmarkStats::from_samples(samples)
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use anyhow::{Result, Ok};
+
+    use super::{BenchmarkStats, run_benchmark};
+    use crate::database::{InMemoryDb, Operation};
+
+    #[test]
+    fn test_stats() -> Result<()> {
+        let samples: Vec<Duration> = (1..=100).rev().map(Duration::from_millis).collect();
+        let stats = BenchmarkStats::from_samples(samples)?;
+
+        assert_eq!(stats.min, Duration::from_millis(1));
+        assert_eq!(stats.max, Duration::from_millis(100));
+        assert_eq!(stats.mean, Duration::from_micros(50_500));
+        assert_eq!(stats.median, Duration::from_millis(50));
+        assert_eq!(stats.p95, Duration::from_millis(95));
+        assert_eq!(stats.p99, Duration::from_millis(99));
+        assert_eq!(stats.stddev.as_micros(), 28_866);
+        assert_eq!(stats.samples[0], Duration::from_millis(100));
+        assert!(BenchmarkStats::from_samples(vec![]).is_err());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_run_benchmark() -> Result<()> {
+        let mut db = InMemoryDb::new();
+        let operation = Operation::GetMostVotedPkgs { number: 10 };
+        let stats = run_benchmark(&mut db, &operation, 20, 5).await?;
+        assert_eq!(stats.samples.len(), 20);
+        assert!(stats.min <= stats.median && stats.median <= stats.max);
+        Ok(())
+    }
+}
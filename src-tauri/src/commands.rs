@@ -1,21 +1,26 @@
 use core::fmt;
-use std::{error::Error, time::Duration, collections::HashMap};
-use serde::{Serialize, Deserialize};
+use std::{error::Error, time::Duration, collections::HashMap, path::Path};
+use serde::{Serialize, Deserialize, de::DeserializeOwned};
 use anyhow::Result;
 
-use crate::{database::{DbResponse, RedisDb, DbActions, SkytableClient, SurrealDbClient}, models::{BasicPackageData, PackageData}};
+use tauri::{State, Window};
 
-#[derive(Serialize, Deserialize, Debug)]
+use crate::{database::{DbResponse, BackendRegistry, Operation}, models::{BasicPackageData, BasicPackagePatch, CatalogStats, DependencyTree, PackageData}, benchmark::{self, BenchmarkStats}, comparison::{self, Comparison}, import::{self, Dataset, ImportProgress, ImportReport}, generator::{self, GeneratorConfig}, history::{HistoryStore, Measurement, Run, RunComparison, RunFilter}, export::{self, ExportFormat}, config::DEFAULT_PROFILE};
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub enum Db {
     SurrealDb,
     Redis,
-    Skytable
+    Skytable,
+    Sqlite,
+    InMemory
 }
 
 #[derive(Serialize, Deserialize, Debug)]
 pub struct QueryCommand {
     target_db: Db,
-    query: String
+    query: String,
+    profile: Option<String>
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -37,83 +42,215 @@ impl From<anyhow::Error> for FrontendError {
     }
 }
 
-// TODO: fix trait object with async
+// Keeps the run in the history along with the settings of its profile; the operation already went through, so a failed
+// write is reported rather than failing the command
+fn record(registry: &BackendRegistry, history: &HistoryStore, target_db: Db, profile: Option<&str>, operation: &Operation, measurement: Measurement) {
+    let recorded = registry.config().profile(&target_db, profile)
+        .and_then(|settings| history.record(target_db, profile.unwrap_or(DEFAULT_PROFILE), &settings, operation, &measurement));
+    if let Err(e) = recorded {
+        eprintln!("Cannot record the run of {:?} in the history: {:#}", target_db, e);
+    }
+}
+
+// Runs a single operation on the backend of the profile and records it, the result is brought back to its type
+async fn run_operation<T: Serialize + DeserializeOwned>(registry: &BackendRegistry, history: &HistoryStore, target_db: Db, profile: Option<&str>, operation: Operation) -> Result<DbResponse<T>> {
+    let mut db = registry.acquire(target_db, profile).await?;
+    let response = operation.run(&mut **db).await;
+    let response = db.check_response(response)?;
+    record(registry, history, target_db, profile, &operation, Measurement::of_response(&response));
+    Ok(DbResponse { result: serde_json::from_value(response.result)?, duration: response.duration, timings: response.timings })
+}
+
 #[tauri::command]
-pub async fn get_query_time(query_command: QueryCommand) -> Result<Duration, FrontendError> {
-    let response = match query_command.target_db {
-        Db::Redis => RedisDb::try_new()?.get_custom_query_time(&query_command.query).await?,
-        Db::Skytable => SkytableClient::try_new()?.get_custom_query_time(&query_command.query).await?,
-        Db::SurrealDb => SurrealDbClient::try_new().await?.get_custom_query_time(&query_command.query).await?
-    };
-    Ok(response)
+pub async fn get_query_time(registry: State<'_, BackendRegistry>, history: State<'_, HistoryStore>, query_command: QueryCommand) -> Result<Duration, FrontendError> {
+    let mut db = registry.acquire(query_command.target_db, query_command.profile.as_deref()).await?;
+    let response = db.get_custom_query_time(&query_command.query).await;
+    let duration = db.check(response)?;
+    let operation = Operation::RunCustomQuery { query: query_command.query };
+    record(&registry, &history, query_command.target_db, query_command.profile.as_deref(), &operation, Measurement::Single { duration, timings: None });
+    Ok(duration)
 }
 
 #[tauri::command]
-pub async fn run_query(query_command: QueryCommand) -> Result<DbResponse<String>, FrontendError> {
-    let response = match query_command.target_db {
-        Db::Redis => RedisDb::try_new()?.run_custom_query(&query_command.query).await?,
-        Db::Skytable => SkytableClient::try_new()?.run_custom_query(&query_command.query).await?,
-        Db::SurrealDb => SurrealDbClient::try_new().await?.run_custom_query(&query_command.query).await?
-    };
-    Ok(response)
+pub async fn run_query(registry: State<'_, BackendRegistry>, history: State<'_, HistoryStore>, query_command: QueryCommand) -> Result<DbResponse<String>, FrontendError> {
+    Ok(run_operation(&registry, &history, query_command.target_db, query_command.profile.as_deref(), Operation::RunCustomQuery { query: query_command.query }).await?)
 }
 
 #[tauri::command]
-pub async fn sort_pkgs_by_field_with_limit(target_db: Db, field: &str, limit_start: u32, limit_end: u32) -> Result<DbResponse<Vec<String>>, FrontendError> {
-    let response = match target_db {
-        Db::Redis => RedisDb::try_new()?.sort_pkgs_by_field_with_limit(field, limit_start, limit_end).await?,
-        Db::Skytable => SkytableClient::try_new()?.sort_pkgs_by_field_with_limit(field, limit_start, limit_end).await?,
-        Db::SurrealDb => SurrealDbClient::try_new().await?.sort_pkgs_by_field_with_limit(field, limit_start, limit_end).await?
-    };
-    Ok(response)
+pub async fn sort_pkgs_by_field_with_limit(registry: State<'_, BackendRegistry>, history: State<'_, HistoryStore>, target_db: Db, profile: Option<&str>, field: &str, limit_start: u32, limit_end: u32) -> Result<DbResponse<Vec<String>>, FrontendError> {
+    Ok(run_operation(&registry, &history, target_db, profile, Operation::SortPkgsByFieldWithLimit { field: field.to_owned(), limit_start, limit_end }).await?)
 }
 
 #[tauri::command]
-pub async fn get_most_voted_pkgs(target_db: Db, number: u32) -> Result<DbResponse<Vec<BasicPackageData>>, FrontendError> {
-    let response = match target_db {
-        Db::Redis => RedisDb::try_new()?.get_most_voted_pkgs(number).await?,
-        Db::Skytable => SkytableClient::try_new()?.get_most_voted_pkgs(number).await?,
-        Db::SurrealDb => SurrealDbClient::try_new().await?.get_most_voted_pkgs(number).await?
-    };
-    Ok(response)
+pub async fn get_most_voted_pkgs(registry: State<'_, BackendRegistry>, history: State<'_, HistoryStore>, target_db: Db, profile: Option<&str>, number: u32) -> Result<DbResponse<Vec<BasicPackageData>>, FrontendError> {
+    Ok(run_operation(&registry, &history, target_db, profile, Operation::GetMostVotedPkgs { number }).await?)
 }
 
 #[tauri::command]
-pub async fn insert_pkg(target_db: Db, pkg: PackageData) -> Result<DbResponse<()>, FrontendError> {
-    let response = match target_db {
-        Db::Redis => RedisDb::try_new()?.insert_pkg(&pkg).await?,
-        Db::Skytable => SkytableClient::try_new()?.insert_pkg(&pkg).await?,
-        Db::SurrealDb => SurrealDbClient::try_new().await?.insert_pkg(&pkg).await?,
-    };
-    Ok(response)
+pub async fn insert_pkg(registry: State<'_, BackendRegistry>, history: State<'_, HistoryStore>, target_db: Db, profile: Option<&str>, pkg: PackageData) -> Result<DbResponse<()>, FrontendError> {
+    Ok(run_operation(&registry, &history, target_db, profile, Operation::InsertPkg { pkg: Box::new(pkg) }).await?)
 }
 
 #[tauri::command]
-pub async fn get_pkg(target_db: Db, name: &str) -> Result<DbResponse<PackageData>, FrontendError> {
-    let response = match target_db {
-        Db::Redis => RedisDb::try_new()?.get_pkg(name).await?,
-        Db::Skytable => SkytableClient::try_new()?.get_pkg(name).await?,
-        Db::SurrealDb => SurrealDbClient::try_new().await?.get_pkg(name).await?,
-    };
-    Ok(response)
+pub async fn insert_pkgs(registry: State<'_, BackendRegistry>, history: State<'_, HistoryStore>, target_db: Db, profile: Option<&str>, pkgs: Vec<PackageData>) -> Result<DbResponse<()>, FrontendError> {
+    Ok(run_operation(&registry, &history, target_db, profile, Operation::InsertPkgs { pkgs }).await?)
 }
 
 #[tauri::command]
-pub async fn remove_comments(target_db: Db, pkg_name: &str) -> Result<DbResponse<()>, FrontendError> {
-    let response = match target_db {
-        Db::Redis => RedisDb::try_new()?.remove_comments(pkg_name).await?,
-        Db::Skytable => SkytableClient::try_new()?.remove_comments(pkg_name).await?,
-        Db::SurrealDb => SurrealDbClient::try_new().await?.remove_comments(pkg_name).await?,
-    };
-    Ok(response)
+pub async fn get_pkg(registry: State<'_, BackendRegistry>, history: State<'_, HistoryStore>, target_db: Db, profile: Option<&str>, name: &str) -> Result<DbResponse<PackageData>, FrontendError> {
+    Ok(run_operation(&registry, &history, target_db, profile, Operation::GetPkg { name: name.to_owned() }).await?)
+}
+
+#[tauri::command]
+pub async fn remove_comments(registry: State<'_, BackendRegistry>, history: State<'_, HistoryStore>, target_db: Db, profile: Option<&str>, pkg_name: &str) -> Result<DbResponse<()>, FrontendError> {
+    Ok(run_operation(&registry, &history, target_db, profile, Operation::RemoveComments { pkg_name: pkg_name.to_owned() }).await?)
+}
+
+#[tauri::command]
+pub async fn delete_pkg(registry: State<'_, BackendRegistry>, history: State<'_, HistoryStore>, target_db: Db, profile: Option<&str>, pkg_name: &str) -> Result<DbResponse<()>, FrontendError> {
+    Ok(run_operation(&registry, &history, target_db, profile, Operation::DeletePkg { pkg_name: pkg_name.to_owned() }).await?)
+}
+
+/// Packages listing `pkg_name` in one of the given dependency groups, in any group when none are given.
+#[tauri::command]
+pub async fn get_reverse_dependencies(registry: State<'_, BackendRegistry>, history: State<'_, HistoryStore>, target_db: Db, profile: Option<&str>, pkg_name: &str, groups: Option<Vec<String>>) -> Result<DbResponse<Vec<String>>, FrontendError> {
+    let groups = groups.unwrap_or_default();
+    Ok(run_operation(&registry, &history, target_db, profile, Operation::GetReverseDependencies { pkg_name: pkg_name.to_owned(), groups }).await?)
+}
+
+/// Transitive dependencies of `pkg_name` with an install order, `max_depth` unset walks the whole graph.
+#[tauri::command]
+pub async fn resolve_dependency_tree(registry: State<'_, BackendRegistry>, history: State<'_, HistoryStore>, target_db: Db, profile: Option<&str>, pkg_name: &str, groups: Option<Vec<String>>, max_depth: Option<u32>) -> Result<DbResponse<DependencyTree>, FrontendError> {
+    let groups = groups.unwrap_or_default();
+    Ok(run_operation(&registry, &history, target_db, profile, Operation::ResolveDependencyTree { pkg_name: pkg_name.to_owned(), groups, max_depth }).await?)
+}
+
+#[tauri::command]
+pub async fn search_pkgs(registry: State<'_, BackendRegistry>, history: State<'_, HistoryStore>, target_db: Db, profile: Option<&str>, query: &str, limit: u32) -> Result<DbResponse<Vec<String>>, FrontendError> {
+    Ok(run_operation(&registry, &history, target_db, profile, Operation::SearchPkgs { query: query.to_owned(), limit }).await?)
+}
+
+#[tauri::command]
+pub async fn get_pkgs_by_maintainer(registry: State<'_, BackendRegistry>, history: State<'_, HistoryStore>, target_db: Db, profile: Option<&str>, maintainer: &str) -> Result<DbResponse<Vec<String>>, FrontendError> {
+    Ok(run_operation(&registry, &history, target_db, profile, Operation::GetPkgsByMaintainer { maintainer: maintainer.to_owned() }).await?)
+}
+
+#[tauri::command]
+pub async fn get_pkgs_by_submitter(registry: State<'_, BackendRegistry>, history: State<'_, HistoryStore>, target_db: Db, profile: Option<&str>, submitter: &str) -> Result<DbResponse<Vec<String>>, FrontendError> {
+    Ok(run_operation(&registry, &history, target_db, profile, Operation::GetPkgsBySubmitter { submitter: submitter.to_owned() }).await?)
+}
+
+#[tauri::command]
+pub async fn get_catalog_stats(registry: State<'_, BackendRegistry>, history: State<'_, HistoryStore>, target_db: Db, profile: Option<&str>) -> Result<DbResponse<CatalogStats>, FrontendError> {
+    Ok(run_operation(&registry, &history, target_db, profile, Operation::GetCatalogStats).await?)
+}
+
+#[tauri::command]
+pub async fn update_pkg_fields(registry: State<'_, BackendRegistry>, history: State<'_, HistoryStore>, target_db: Db, profile: Option<&str>, pkg_name: &str, patch: BasicPackagePatch) -> Result<DbResponse<()>, FrontendError> {
+    Ok(run_operation(&registry, &history, target_db, profile, Operation::UpdatePkgFields { pkg_name: pkg_name.to_owned(), patch }).await?)
 }
 
 #[tauri::command]
-pub async fn get_packages_occurences_in_deps(target_db: Db, pkg_names: Vec<String>) -> Result<DbResponse<HashMap<String, u32>>, FrontendError> {
-    let response = match target_db {
-        Db::Redis => RedisDb::try_new()?.get_packages_occurences_in_deps(&pkg_names).await?,
-        Db::Skytable => SkytableClient::try_new()?.get_packages_occurences_in_deps(&pkg_names).await?,
-        Db::SurrealDb => SurrealDbClient::try_new().await?.get_packages_occurences_in_deps(&pkg_names).await?,
+pub async fn increment_votes(registry: State<'_, BackendRegistry>, history: State<'_, HistoryStore>, target_db: Db, profile: Option<&str>, pkg_name: &str, delta: i32) -> Result<DbResponse<i32>, FrontendError> {
+    Ok(run_operation(&registry, &history, target_db, profile, Operation::IncrementVotes { pkg_name: pkg_name.to_owned(), delta }).await?)
+}
+
+/// Reports keys no package owns anymore, deleting them when `remove` is set.
+#[tauri::command]
+pub async fn find_orphaned_keys(registry: State<'_, BackendRegistry>, history: State<'_, HistoryStore>, target_db: Db, profile: Option<&str>, remove: bool) -> Result<DbResponse<Vec<String>>, FrontendError> {
+    Ok(run_operation(&registry, &history, target_db, profile, Operation::FindOrphanedKeys { remove }).await?)
+}
+
+#[tauri::command]
+pub async fn get_packages_occurences_in_deps(registry: State<'_, BackendRegistry>, history: State<'_, HistoryStore>, target_db: Db, profile: Option<&str>, pkg_names: Vec<String>, groups: Option<Vec<String>>) -> Result<DbResponse<HashMap<String, u32>>, FrontendError> {
+    let groups = groups.unwrap_or_default();
+    Ok(run_operation(&registry, &history, target_db, profile, Operation::GetPackagesOccurencesInDeps { pkg_names, groups }).await?)
+}
+
+#[tauri::command]
+pub async fn run_benchmark(registry: State<'_, BackendRegistry>, history: State<'_, HistoryStore>, target_dbs: Vec<Db>, profile: Option<&str>, operation: Operation, iterations: u32, warmup: u32) -> Result<HashMap<Db, BenchmarkStats>, FrontendError> {
+    let mut result = HashMap::new();
+    for target_db in target_dbs {
+        let mut db = registry.acquire(target_db, profile).await?;
+        let stats = benchmark::run_benchmark(&mut **db, &operation, iterations, warmup).await;
+        let stats = db.check(stats)?;
+        record(&registry, &history, target_db, profile, &operation, Measurement::Benchmark { iterations, warmup, stats: stats.clone() });
+        result.insert(target_db, stats);
+    }
+    Ok(result)
+}
+
+#[tauri::command]
+pub async fn compare_operation(registry: State<'_, BackendRegistry>, history: State<'_, HistoryStore>, profile: Option<&str>, operation: Operation) -> Result<Comparison, FrontendError> {
+    let comparison = comparison::compare(&registry, &operation, profile).await;
+    for (db, outcome) in &comparison.outcomes {
+        if let Some(duration) = outcome.duration {
+            record(&registry, &history, *db, profile, &operation, Measurement::Single { duration, timings: outcome.timings });
+        }
+    }
+    Ok(comparison)
+}
+
+/// Loads a dataset into the given backends (all registered ones by default), emitting `import-progress` events.
+/// Packages are inserted one by one unless a `batch_size` is given.
+#[tauri::command]
+pub async fn import_dataset(window: Window, registry: State<'_, BackendRegistry>, history: State<'_, HistoryStore>, path: &str, target_dbs: Option<Vec<Db>>, profile: Option<&str>, batch_size: Option<usize>) -> Result<Vec<ImportReport>, FrontendError> {
+    let dataset = Dataset::read(Path::new(path))?;
+    let targets = target_dbs.unwrap_or_else(|| registry.registered());
+    let on_progress = move |progress: ImportProgress| _ = window.emit("import-progress", progress);
+    let reports = import::import(&registry, &dataset, targets, profile, batch_size.unwrap_or(1), &on_progress).await;
+    // Later runs against these backends are tagged with the dataset, even if some of its packages failed to load
+    let fingerprint = dataset.fingerprint()?;
+    for report in reports.iter().filter(|report| report.imported > 0) {
+        history.set_dataset(report.db, profile.unwrap_or(DEFAULT_PROFILE), &fingerprint)?;
+    }
+    Ok(reports)
+}
+
+/// Writes a synthetic dataset as a JSON-lines file which can be loaded with `import_dataset`, returns the number of packages.
+#[tauri::command]
+pub async fn generate_dataset(config: GeneratorConfig, path: &str) -> Result<usize, FrontendError> {
+    Ok(generator::write_dataset(config, Path::new(path))?)
+}
+
+#[tauri::command]
+pub async fn reconnect(registry: State<'_, BackendRegistry>, target_db: Db, profile: Option<&str>) -> Result<Duration, FrontendError> {
+    Ok(registry.reconnect(target_db, profile).await?)
+}
+
+#[tauri::command]
+pub fn get_connection_profiles(registry: State<'_, BackendRegistry>) -> HashMap<Db, Vec<String>> {
+    registry.registered()
+        .into_iter()
+        .map(|db| (db, registry.config().profile_names(&db)))
+        .collect()
+}
+
+/// Past runs matching the filter, most recent first; every run is listed without a filter.
+#[tauri::command]
+pub fn list_runs(history: State<'_, HistoryStore>, filter: Option<RunFilter>) -> Result<Vec<Run>, FrontendError> {
+    Ok(history.list(&filter.unwrap_or_default())?)
+}
+
+/// Deletes the given runs from the history, returns how many were deleted.
+#[tauri::command]
+pub fn delete_runs(history: State<'_, HistoryStore>, ids: Vec<i64>) -> Result<usize, FrontendError> {
+    Ok(history.delete(&ids)?)
+}
+
+/// Compares past runs against the first of `ids`.
+#[tauri::command]
+pub fn compare_runs(history: State<'_, HistoryStore>, ids: Vec<i64>) -> Result<RunComparison, FrontendError> {
+    Ok(history.compare(&ids)?)
+}
+
+/// Writes the given runs, or the ones matching the filter, to `path`; the format is taken from the extension if not given.
+/// Returns the number of exported runs.
+#[tauri::command]
+pub fn export_runs(history: State<'_, HistoryStore>, path: &str, ids: Option<Vec<i64>>, filter: Option<RunFilter>, format: Option<ExportFormat>) -> Result<usize, FrontendError> {
+    let runs = match ids {
+        Some(ids) => ids.into_iter().map(|id| history.get(id)).collect::<Result<Vec<_>>>()?,
+        None => history.list(&filter.unwrap_or_default())?,
     };
-    Ok(response)
-}
\ No newline at end of file
+    Ok(export::export(&runs, format, Path::new(path))?)
+}
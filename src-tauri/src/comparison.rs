@@ -0,0 +1,117 @@
+use std::{collections::HashMap, time::Duration};
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::{commands::Db, database::{BackendRegistry, DbResponse, Operation, Timings}};
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct BackendOutcome {
+    pub duration: Option<Duration>,
+    pub timings: Option<Timings>,
+    pub result: Option<Value>,
+    pub error: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Comparison {
+    pub outcomes: HashMap<Db, BackendOutcome>,
+    // `None` when the operation returns backend specific results, e.g. custom queries
+    pub consistent: Option<bool>,
+    // Backends whose result differs from the one returned by most backends, all of them when no result has a strict majority
+    pub mismatched: Vec<Db>,
+}
+
+impl Comparison {
+    pub fn from_responses(operation: &Operation, responses: HashMap<Db, Result<DbResponse<Value>>>) -> Self {
+        let mut outcomes = HashMap::new();
+        let mut canonical: Vec<(Db, Option<Value>)> = Vec::new();
+
+        // Sorted so that the order of `mismatched` does not depend on the map order
+        let mut responses: Vec<_> = responses.into_iter().collect();
+        responses.sort_by_key(|(db, _)| *db);
+        for (db, response) in responses {
+            let outcome = match response {
+                Ok(response) => {
+                    canonical.push((db, operation.canonical_result(response.result.clone())));
+                    BackendOutcome { duration: Some(response.duration), timings: Some(response.timings), result: Some(response.result), error: None }
+                },
+                Err(e) => BackendOutcome { duration: None, timings: None, result: None, error: Some(e.to_string()) },
+            };
+            outcomes.insert(db, outcome);
+        }
+
+        if canonical.iter().any(|(_, value)| value.is_none()) {
+            return Self { outcomes, consistent: None, mismatched: vec![] };
+        }
+
+        let majority = canonical.iter()
+            .map(|(_, value)| value)
+            .find(|value| canonical.iter().filter(|(_, other)| other == *value).count() * 2 > canonical.len());
+        let mismatched: Vec<Db> = canonical.iter()
+            .filter(|(_, value)| Some(value) != majority)
+            .map(|(db, _)| *db)
+            .collect();
+
+        Self { outcomes, consistent: Some(mismatched.is_empty()), mismatched }
+    }
+}
+
+/// Runs the operation once against every registered backend; failures are reported per backend.
+pub async fn compare(registry: &BackendRegistry, operation: &Operation, profile: Option<&str>) -> Comparison {
+    let mut responses = HashMap::new();
+    for db in registry.registered() {
+        let response = match registry.acquire(db, profile).await {
+            Ok(mut backend) => {
+                let response = operation.run(&mut **backend).await;
+                backend.check_response(response)
+            },
+            Err(e) => Err(e),
+        };
+        responses.insert(db, response);
+    }
+    Comparison::from_responses(operation, responses)
+}
+
+#[cfg(test)]
+mod test {
+    use std::{collections::HashMap, time::Duration};
+
+    use anyhow::anyhow;
+    use serde_json::json;
+
+    use super::Comparison;
+    use crate::{commands::Db, database::{DbResponse, Operation}};
+
+    #[test]
+    fn test_from_responses() {
+        let operation = Operation::GetMostVotedPkgs { number: 1 };
+        let response = |result| Ok(DbResponse { result, duration: Duration::from_millis(1), timings: Default::default() });
+        let responses = HashMap::from([
+            (Db::Redis, response(json!(["a"]))),
+            (Db::Sqlite, response(json!(["a"]))),
+            (Db::Skytable, response(json!(["b"]))),
+            (Db::SurrealDb, Err(anyhow!("Connection refused"))),
+        ]);
+
+        let comparison = Comparison::from_responses(&operation, responses);
+        assert_eq!(comparison.consistent, Some(false));
+        assert_eq!(comparison.mismatched, vec![Db::Skytable]);
+        assert_eq!(comparison.outcomes[&Db::SurrealDb].error.as_deref(), Some("Connection refused"));
+
+        let responses = HashMap::from([
+            (Db::Redis, response(json!(["a"]))),
+            (Db::Sqlite, response(json!(["b"]))),
+            (Db::InMemory, response(json!(["b"]))),
+            (Db::Skytable, response(json!(["a"]))),
+        ]);
+        let comparison = Comparison::from_responses(&operation, responses);
+        assert_eq!(comparison.consistent, Some(false));
+        assert_eq!(comparison.mismatched, vec![Db::Redis, Db::Skytable, Db::Sqlite, Db::InMemory]);
+
+        let operation = Operation::RunCustomQuery { query: "KEYS".into() };
+        let responses = HashMap::from([(Db::Redis, response(json!("a"))), (Db::InMemory, response(json!("b")))]);
+        assert_eq!(Comparison::from_responses(&operation, responses).consistent, None);
+    }
+}
@@ -0,0 +1,199 @@
+use std::{collections::HashMap, env, fs, path::PathBuf};
+
+use anyhow::{Context, Result, anyhow, bail};
+use serde::{Deserialize, Serialize};
+
+use crate::commands::Db;
+
+const CONFIG_PATH_VAR: &str = "ZTPD_CONFIG";
+const DEFAULT_CONFIG_PATH: &str = "connections.json";
+pub const DEFAULT_PROFILE: &str = "default";
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ConnectionProfile {
+    #[serde(default)]
+    pub host: String,
+    #[serde(default)]
+    pub port: u16,
+    pub username: Option<String>,
+    pub password: Option<String>,
+    // Only used by SurrealDB
+    pub namespace: Option<String>,
+    // Database name for SurrealDB, path to the database file for SQLite, store name for the in-memory backend
+    pub database: Option<String>,
+    #[serde(default)]
+    pub tls: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub redis: Option<RedisSettings>,
+}
+
+/// Settings only read by the Redis backend, under the `redis` key of a profile.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct RedisSettings {
+    #[serde(default)]
+    pub sort_strategy: RedisSortStrategy,
+}
+
+/// How Redis orders packages in `sort_pkgs_by_field_with_limit`.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum RedisSortStrategy {
+    /// `SORT` over the set of every package, nothing to maintain on writes.
+    #[default]
+    Sort,
+    /// `ZRANGE` over sorted sets of the indexed fields, kept up to date on every write; other fields fall back to `SORT`.
+    SortedSet,
+}
+
+impl ConnectionProfile {
+    fn builtin(db: &Db) -> Self {
+        match db {
+            Db::Redis => ConnectionProfile {
+                host: "127.0.0.1".into(),
+                port: 6379,
+                username: Some("default".into()),
+                password: Some("redis".into()),
+                namespace: None,
+                database: None,
+                tls: false,
+                redis: Some(RedisSettings::default()),
+            },
+            Db::Skytable => ConnectionProfile {
+                host: "127.0.0.1".into(),
+                port: 2003,
+                username: None,
+                password: None,
+                namespace: None,
+                database: None,
+                tls: false,
+                redis: None,
+            },
+            Db::SurrealDb => ConnectionProfile {
+                host: "127.0.0.1".into(),
+                port: 8000,
+                username: Some("root".into()),
+                password: Some("root".into()),
+                namespace: Some("aur".into()),
+                database: Some("packages".into()),
+                tls: false,
+                redis: None,
+            },
+            Db::Sqlite => ConnectionProfile {
+                host: String::new(),
+                port: 0,
+                username: None,
+                password: None,
+                namespace: None,
+                database: Some("packages.sqlite".into()),
+                tls: false,
+                redis: None,
+            },
+            Db::InMemory => ConnectionProfile {
+                host: String::new(),
+                port: 0,
+                username: None,
+                password: None,
+                namespace: None,
+                database: Some("default".into()),
+                tls: false,
+                redis: None,
+            },
+        }
+    }
+
+    fn apply_env(&mut self, db: &Db) -> Result<()> {
+        let prefix = env_prefix(db);
+        let var = |name: &str| env::var(format!("{}_{}", prefix, name)).ok();
+
+        if let Some(host) = var("HOST") {
+            self.host = host;
+        }
+        if let Some(port) = var("PORT") {
+            self.port = port.parse().with_context(|| format!("Invalid {}_PORT value: {}", prefix, port))?;
+        }
+        if let Some(username) = var("USERNAME") {
+            self.username = Some(username);
+        }
+        if let Some(password) = var("PASSWORD") {
+            self.password = Some(password);
+        }
+        if let Some(namespace) = var("NAMESPACE") {
+            self.namespace = Some(namespace);
+        }
+        if let Some(database) = var("DATABASE") {
+            self.database = Some(database);
+        }
+        if let Some(tls) = var("TLS") {
+            self.tls = matches!(tls.to_lowercase().as_str(), "1" | "true" | "yes");
+        }
+        if let Some(strategy) = var("SORT_STRATEGY").filter(|_| *db == Db::Redis) {
+            self.redis.get_or_insert_with(RedisSettings::default).sort_strategy = match strategy.to_lowercase().as_str() {
+                "sort" => RedisSortStrategy::Sort,
+                "sortedset" | "sorted_set" | "zset" => RedisSortStrategy::SortedSet,
+                _ => bail!("Invalid {}_SORT_STRATEGY value: {}", prefix, strategy),
+            };
+        }
+        Ok(())
+    }
+}
+
+fn env_prefix(db: &Db) -> &'static str {
+    match db {
+        Db::Redis => "REDIS",
+        Db::Skytable => "SKYTABLE",
+        Db::SurrealDb => "SURREALDB",
+        Db::Sqlite => "SQLITE",
+        Db::InMemory => "INMEMORY",
+    }
+}
+
+/// Named connection profiles for every database, loaded from a JSON file:
+/// `{ "Redis": { "default": { "host": "127.0.0.1", "port": 6379, ... } } }`.
+/// Environment variables (e.g. `REDIS_PORT`, `SURREALDB_NAMESPACE`) override the default profile.
+#[derive(Serialize, Deserialize, Debug, Default)]
+#[serde(transparent)]
+pub struct ConnectionConfig {
+    profiles: HashMap<Db, HashMap<String, ConnectionProfile>>,
+}
+
+impl ConnectionConfig {
+    pub fn load() -> Result<Self> {
+        let path = env::var(CONFIG_PATH_VAR)
+            .map(PathBuf::from)
+            .unwrap_or_else(|_| PathBuf::from(DEFAULT_CONFIG_PATH));
+
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+
+        let content = fs::read_to_string(&path)
+            .with_context(|| format!("Cannot read connection config: {}", path.display()))?;
+        serde_json::from_str(&content)
+            .with_context(|| format!("Cannot parse connection config: {}", path.display()))
+    }
+
+    pub fn profile(&self, db: &Db, name: Option<&str>) -> Result<ConnectionProfile> {
+        let name = name.unwrap_or(DEFAULT_PROFILE);
+        let configured = self.profiles.get(db).and_then(|profiles| profiles.get(name));
+
+        if name != DEFAULT_PROFILE {
+            return configured
+                .cloned()
+                .ok_or(anyhow!("Unknown connection profile '{}' for {:?}", name, db));
+        }
+
+        let mut profile = configured.cloned().unwrap_or_else(|| ConnectionProfile::builtin(db));
+        profile.apply_env(db)?;
+        Ok(profile)
+    }
+
+    pub fn profile_names(&self, db: &Db) -> Vec<String> {
+        let mut names: Vec<String> = self.profiles.get(db)
+            .map(|profiles| profiles.keys().cloned().collect())
+            .unwrap_or_default();
+        if !names.iter().any(|name| name == DEFAULT_PROFILE) {
+            names.push(DEFAULT_PROFILE.to_owned());
+        }
+        names.sort();
+        names
+    }
+}
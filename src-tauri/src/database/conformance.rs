@@ -0,0 +1,268 @@
+//! Checks the real backends against `InMemoryDb`, which serves as the reference implementation.
+//! Tests of server backends need a running database and are ignored by default (`cargo test -- --ignored`).
+
+use anyhow::{Result, Ok};
+
+use crate::{models::{PackageData, BasicPackageData, BasicPackagePatch, AdditionalPackageData, Comment, PackageDependency}, config::{ConnectionConfig, RedisSettings, RedisSortStrategy}, commands::Db};
+
+use super::{DbActions, InMemoryDb, Connect, RedisDb, SkytableClient, SurrealDbClient, SqliteDb};
+
+pub fn fixture_pkgs() -> Vec<PackageData> {
+    (0..5).map(|i| PackageData {
+        basic: BasicPackageData {
+            name: format!("pkg-{}", i),
+            version: format!("1.{}", i),
+            path_to_additional_data: format!("/pkg-{}", i),
+            votes: i * 10,
+            popularity: i as f32 / 2.0,
+            description: format!("Fixture package number {}", i),
+            maintainer: format!("maintainer-{}", i % 2),
+            last_updated: format!("2023-05-0{} 12:00 (UTC)", i + 1),
+        },
+        additional: AdditionalPackageData {
+            git_clone_url: format!("https://aur.archlinux.org/pkg-{}.git", i),
+            keywords: Some("fixture".into()),
+            license: Some("MIT".into()),
+            confilcts: None,
+            provides: None,
+            submitter: "submitter".into(),
+            first_submitted: "2020-01-01 12:00 (UTC)".into(),
+        },
+        dependencies: match i {
+            0 => vec![],
+            _ => vec![PackageDependency { group: "depends".into(), packages: vec![format!("pkg-{}", i - 1)] }],
+        },
+        comments: (0..2)
+            .map(|c| Comment { header: format!("user-{} commented", c), content: format!("Comment {} on pkg-{}", c, i) })
+            .collect(),
+    }).collect()
+}
+
+// Copies of `pkg-2` under other names, so that every sortable field has ties, broken by descending name. They are
+// inserted out of name order, so an insertion order tie-break shows
+fn tied_pkgs() -> Vec<PackageData> {
+    let pkg = fixture_pkgs().swap_remove(2);
+    ["pkg-2b", "pkg-2a"].iter().map(|name| {
+        let mut tied = pkg.clone();
+        tied.basic.name = name.to_string();
+        tied.basic.path_to_additional_data = format!("/{}", name);
+        tied.additional.git_clone_url = format!("https://aur.archlinux.org/{}.git", name);
+        tied.dependencies = vec![];
+        tied
+    }).collect()
+}
+
+// Comments and dependency groups are stored in sets by some backends, so their order is not part of the contract
+fn normalize(mut pkg: PackageData) -> PackageData {
+    pkg.comments.sort_by(|a, b| a.header.cmp(&b.header));
+    pkg.dependencies.sort_by(|a, b| a.group.cmp(&b.group));
+    pkg
+}
+
+async fn assert_matches_oracle(backend: &mut (dyn DbActions + Send)) -> Result<()> {
+    let mut oracle = InMemoryDb::new();
+    let pkgs: Vec<PackageData> = fixture_pkgs().into_iter().chain(tied_pkgs()).collect();
+    // Leftovers of an interrupted run would make server backends diverge from the fresh oracle
+    for pkg in &pkgs {
+        backend.delete_pkg(&pkg.basic.name).await?;
+    }
+
+    // Part of the fixture goes through the batch insert, so both write paths are covered
+    let (single, batch) = pkgs.split_at(2);
+    for pkg in single {
+        oracle.insert_pkg(pkg).await?;
+        backend.insert_pkg(pkg).await?;
+    }
+    oracle.insert_pkgs(batch).await?;
+    backend.insert_pkgs(batch).await?;
+
+    for field in ["votes", "popularity", "name", "last_updated"] {
+        for (limit_start, limit_end) in [(0, 3), (2, 4)] {
+            assert_eq!(
+                backend.sort_pkgs_by_field_with_limit(field, limit_start, limit_end).await?.result,
+                oracle.sort_pkgs_by_field_with_limit(field, limit_start, limit_end).await?.result,
+                "sort_pkgs_by_field_with_limit({}, {}, {})", field, limit_start, limit_end
+            );
+        }
+    }
+
+    assert_eq!(backend.get_most_voted_pkgs(3).await?.result, oracle.get_most_voted_pkgs(3).await?.result);
+
+    for pkg in &pkgs {
+        let name = &pkg.basic.name;
+        assert_eq!(normalize(backend.get_pkg(name).await?.result), normalize(oracle.get_pkg(name).await?.result));
+    }
+
+    for (name, groups) in [("pkg-1", vec![]), ("pkg-1", vec!["depends".to_string()]), ("pkg-1", vec!["makedepends".to_string()]), ("missing", vec![])] {
+        assert_eq!(
+            backend.get_reverse_dependencies(name, &groups).await?.result,
+            oracle.get_reverse_dependencies(name, &groups).await?.result,
+            "get_reverse_dependencies({}, {:?})", name, groups
+        );
+    }
+
+    for (groups, max_depth) in [(vec![], None), (vec![], Some(2)), (vec!["depends".to_string()], Some(0)), (vec!["makedepends".to_string()], None)] {
+        assert_eq!(
+            backend.resolve_dependency_tree("pkg-4", &groups, max_depth).await?.result,
+            oracle.resolve_dependency_tree("pkg-4", &groups, max_depth).await?.result,
+            "resolve_dependency_tree({:?}, {:?})", groups, max_depth
+        );
+    }
+    assert!(backend.resolve_dependency_tree("missing", &[], None).await.is_err());
+    assert!(backend.contains_pkg("pkg-0").await?.result && !backend.contains_pkg("missing").await?.result);
+
+    for (query, limit) in [("fixture", 3), ("PKG num", 10), ("pkg-2", 10), ("missing", 10)] {
+        assert_eq!(
+            backend.search_pkgs(query, limit).await?.result,
+            oracle.search_pkgs(query, limit).await?.result,
+            "search_pkgs({}, {})", query, limit
+        );
+    }
+
+    for owner in ["maintainer-0", "maintainer-1", "missing"] {
+        assert_eq!(backend.get_pkgs_by_maintainer(owner).await?.result, oracle.get_pkgs_by_maintainer(owner).await?.result, "get_pkgs_by_maintainer({})", owner);
+    }
+    assert_eq!(backend.get_pkgs_by_submitter("submitter").await?.result, oracle.get_pkgs_by_submitter("submitter").await?.result);
+
+    let names: Vec<String> = ["pkg-0", "pkg-3", "pkg-4", "missing"].iter().map(|name| name.to_string()).collect();
+    for groups in [vec![], vec!["depends".to_string()], vec!["makedepends".to_string(), "optdepends".to_string()]] {
+        assert_eq!(
+            backend.get_packages_occurences_in_deps(&names, &groups).await?.result,
+            oracle.get_packages_occurences_in_deps(&names, &groups).await?.result,
+            "get_packages_occurences_in_deps({:?})", groups
+        );
+    }
+
+    backend.remove_comments("pkg-1").await?;
+    oracle.remove_comments("pkg-1").await?;
+    assert_eq!(backend.get_pkg("pkg-1").await?.result, oracle.get_pkg("pkg-1").await?.result);
+    // Other data on a shared server may have orphans of its own, only the fixture must leave none
+    let orphans = backend.find_orphaned_keys(false).await?.result;
+    let names: Vec<&str> = pkgs.iter().map(|pkg| pkg.basic.name.as_str()).collect();
+    assert!(!orphans.iter().any(|key| key.split(':').any(|part| names.contains(&part))), "orphaned keys: {:?}", orphans);
+
+    let patch = BasicPackagePatch { version: Some("2.0".into()), popularity: Some(9.5), maintainer: Some("maintainer-1".into()), ..Default::default() };
+    backend.update_pkg_fields("pkg-2", &patch).await?;
+    oracle.update_pkg_fields("pkg-2", &patch).await?;
+    assert_eq!(backend.get_pkg("pkg-2").await?.result.basic, oracle.get_pkg("pkg-2").await?.result.basic);
+    assert!(backend.update_pkg_fields("missing", &patch).await.is_err());
+    for owner in ["maintainer-0", "maintainer-1"] {
+        assert_eq!(backend.get_pkgs_by_maintainer(owner).await?.result, oracle.get_pkgs_by_maintainer(owner).await?.result, "get_pkgs_by_maintainer({})", owner);
+    }
+
+    // A new description replaces the search words taken from the old one
+    let patch = BasicPackagePatch { description: Some("Renamed helper".into()), ..Default::default() };
+    backend.update_pkg_fields("pkg-1", &patch).await?;
+    oracle.update_pkg_fields("pkg-1", &patch).await?;
+    for query in ["renamed", "fixture"] {
+        assert_eq!(backend.search_pkgs(query, 10).await?.result, oracle.search_pkgs(query, 10).await?.result, "search_pkgs({})", query);
+    }
+
+    assert_eq!(backend.increment_votes("pkg-2", 5).await?.result, oracle.increment_votes("pkg-2", 5).await?.result);
+    assert_eq!(backend.increment_votes("pkg-2", -25).await?.result, 0);
+    oracle.increment_votes("pkg-2", -25).await?;
+    assert!(backend.increment_votes("missing", 1).await.is_err());
+    // Votes stay within the i32 range, a rejected increment leaves them unchanged
+    assert_eq!(backend.increment_votes("pkg-2", i32::MAX).await?.result, i32::MAX);
+    assert!(backend.increment_votes("pkg-2", 1).await.is_err());
+    assert_eq!(backend.get_pkg("pkg-2").await?.result.basic.votes, i32::MAX);
+    assert_eq!(backend.increment_votes("pkg-2", -i32::MAX).await?.result, 0);
+    assert_eq!(backend.increment_votes("pkg-2", i32::MIN).await?.result, i32::MIN);
+    assert!(backend.increment_votes("pkg-2", -1).await.is_err());
+    assert_eq!(backend.increment_votes("pkg-2", i32::MAX).await?.result, -1);
+    assert_eq!(backend.increment_votes("pkg-2", 1).await?.result, 0);
+    assert_eq!(backend.get_most_voted_pkgs(5).await?.result, oracle.get_most_voted_pkgs(5).await?.result);
+    assert_eq!(backend.get_catalog_stats().await?.result, oracle.get_catalog_stats().await?.result);
+
+    // Inserting a stored package replaces it, through both write paths, and a batch keeps the last copy of a package
+    let mut replaced = pkgs[3].clone();
+    replaced.basic.votes = 45;
+    replaced.basic.maintainer = "maintainer-2".into();
+    replaced.basic.description = "Replaced helper".into();
+    replaced.dependencies = vec![PackageDependency { group: "makedepends".into(), packages: vec!["pkg-0".into()] }];
+    replaced.comments.truncate(1);
+    backend.insert_pkg(&replaced).await?;
+    oracle.insert_pkg(&replaced).await?;
+    assert_replaced_like_oracle(backend, &mut oracle).await?;
+    let batch = [pkgs[2].clone(), replaced, pkgs[3].clone()];
+    backend.insert_pkgs(&batch).await?;
+    oracle.insert_pkgs(&batch).await?;
+    assert_replaced_like_oracle(backend, &mut oracle).await?;
+
+    backend.delete_pkg("pkg-4").await?;
+    oracle.delete_pkg("pkg-4").await?;
+    assert!(backend.get_pkg("pkg-4").await.is_err());
+    assert!(backend.search_pkgs("pkg 4", 10).await?.result.is_empty());
+    assert_eq!(backend.get_pkgs_by_submitter("submitter").await?.result, oracle.get_pkgs_by_submitter("submitter").await?.result);
+    // The deleted package no longer depends on anything, packages depending on it keep their entries
+    assert!(backend.get_reverse_dependencies("pkg-3", &[]).await?.result.is_empty());
+    assert_eq!(backend.get_reverse_dependencies("pkg-2", &[]).await?.result, vec!["pkg-3"]);
+    for field in ["votes", "popularity", "last_updated"] {
+        assert_eq!(
+            backend.sort_pkgs_by_field_with_limit(field, 0, 5).await?.result,
+            oracle.sort_pkgs_by_field_with_limit(field, 0, 5).await?.result,
+            "sort_pkgs_by_field_with_limit({}) after delete", field
+        );
+    }
+
+    for pkg in &pkgs {
+        backend.delete_pkg(&pkg.basic.name).await?;
+    }
+    Ok(())
+}
+
+async fn assert_replaced_like_oracle(backend: &mut (dyn DbActions + Send), oracle: &mut InMemoryDb) -> Result<()> {
+    for name in ["pkg-2", "pkg-3"] {
+        assert_eq!(normalize(backend.get_pkg(name).await?.result), normalize(oracle.get_pkg(name).await?.result), "get_pkg({})", name);
+    }
+    for name in ["pkg-0", "pkg-2"] {
+        assert_eq!(backend.get_reverse_dependencies(name, &[]).await?.result, oracle.get_reverse_dependencies(name, &[]).await?.result, "get_reverse_dependencies({})", name);
+    }
+    for owner in ["maintainer-1", "maintainer-2"] {
+        assert_eq!(backend.get_pkgs_by_maintainer(owner).await?.result, oracle.get_pkgs_by_maintainer(owner).await?.result, "get_pkgs_by_maintainer({})", owner);
+    }
+    for query in ["replaced", "fixture"] {
+        assert_eq!(backend.search_pkgs(query, 10).await?.result, oracle.search_pkgs(query, 10).await?.result, "search_pkgs({})", query);
+    }
+    assert_eq!(backend.sort_pkgs_by_field_with_limit("votes", 0, 10).await?.result, oracle.sort_pkgs_by_field_with_limit("votes", 0, 10).await?.result);
+    assert_eq!(backend.get_catalog_stats().await?.result, oracle.get_catalog_stats().await?.result);
+    Ok(())
+}
+
+async fn connect<T: Connect>(db: Db) -> Result<T> {
+    let profile = ConnectionConfig::default().profile(&db, None)?;
+    T::connect(&profile).await
+}
+
+#[tokio::test]
+async fn sqlite_matches_oracle() -> Result<()> {
+    let mut profile = ConnectionConfig::default().profile(&Db::Sqlite, None)?;
+    profile.database = Some(":memory:".into());
+    assert_matches_oracle(&mut SqliteDb::try_new(&profile)?).await
+}
+
+#[tokio::test]
+#[ignore = "requires a running Redis server"]
+async fn redis_matches_oracle() -> Result<()> {
+    assert_matches_oracle(&mut connect::<RedisDb>(Db::Redis).await?).await
+}
+
+#[tokio::test]
+#[ignore = "requires a running Redis server"]
+async fn redis_sorted_sets_match_oracle() -> Result<()> {
+    let mut profile = ConnectionConfig::default().profile(&Db::Redis, None)?;
+    profile.redis = Some(RedisSettings { sort_strategy: RedisSortStrategy::SortedSet });
+    assert_matches_oracle(&mut RedisDb::try_new(&profile).await?).await
+}
+
+#[tokio::test]
+#[ignore = "requires a running Skytable server"]
+async fn skytable_matches_oracle() -> Result<()> {
+    assert_matches_oracle(&mut connect::<SkytableClient>(Db::Skytable).await?).await
+}
+
+#[tokio::test]
+#[ignore = "requires a running SurrealDB server"]
+async fn surreal_matches_oracle() -> Result<()> {
+    assert_matches_oracle(&mut connect::<SurrealDbClient>(Db::SurrealDb).await?).await
+}
@@ -0,0 +1,133 @@
+use std::collections::{HashMap, HashSet, VecDeque};
+
+use anyhow::{Result, bail};
+
+use crate::models::{DependencyTree, PackageData};
+
+/// Names of the packages listed in the dependency `groups` of the package (any group when empty).
+pub fn dependency_names(pkg: &PackageData, groups: &[String]) -> Vec<String> {
+    pkg.dependencies.iter()
+        .filter(|dep| groups.is_empty() || groups.contains(&dep.group))
+        .flat_map(|dep| dep.packages.iter().cloned())
+        .collect()
+}
+
+/// Resolves the tree of `root` from the direct dependencies of the packages fetched by a backend.
+/// A package absent from `dependencies_of` is not in the database. Backends may fetch more than `max_depth` levels,
+/// packages are only expanded up to it, but those found at `max_depth` must be present to be told apart from missing ones.
+pub fn resolve<'a>(root: &'a str, max_depth: Option<u32>, dependencies_of: &'a HashMap<String, Vec<String>>) -> Result<DependencyTree> {
+    if !dependencies_of.contains_key(root) {
+        bail!("Package {} not found", root);
+    }
+
+    // Breadth first, so every package is expanded at its shortest distance from the root
+    let mut depths: HashMap<&str, u32> = HashMap::from([(root, 0)]);
+    let mut edges: HashMap<&str, Vec<&str>> = HashMap::new();
+    let mut queue = VecDeque::from([root]);
+    while let Some(name) = queue.pop_front() {
+        let depth = depths[name];
+        if max_depth.is_some_and(|max| depth >= max) {
+            continue;
+        }
+        let Some(deps) = dependencies_of.get(name) else {
+            continue;
+        };
+        // Sorted, so the walk below and with it the install order is the same for every backend
+        let mut deps: Vec<&str> = deps.iter().map(String::as_str).collect();
+        deps.sort_unstable();
+        deps.dedup();
+        for &dep in &deps {
+            if !depths.contains_key(dep) {
+                depths.insert(dep, depth + 1);
+                queue.push_back(dep);
+            }
+        }
+        edges.insert(name, deps);
+    }
+
+    // Depth first walk emitting packages once all of their dependencies are emitted, a dependency already on the path closes a cycle
+    let mut install_order = vec![];
+    let mut cycles = vec![];
+    let mut done: HashSet<&str> = HashSet::new();
+    // Packages on the current path with the index of their next dependency to visit
+    let mut path: Vec<(&str, usize)> = vec![(root, 0)];
+    while let Some(&(name, next)) = path.last() {
+        match edges.get(name).and_then(|deps| deps.get(next)) {
+            Some(&dep) => {
+                if let Some(last) = path.last_mut() {
+                    last.1 += 1;
+                }
+                if let Some(pos) = path.iter().position(|&(n, _)| n == dep) {
+                    cycles.push(rotate_to_smallest(path[pos..].iter().map(|&(n, _)| n.to_owned()).collect()));
+                } else if !done.contains(dep) {
+                    path.push((dep, 0));
+                }
+            },
+            None => {
+                done.insert(name);
+                if dependencies_of.contains_key(name) {
+                    install_order.push(name.to_owned());
+                }
+                path.pop();
+            },
+        }
+    }
+    cycles.sort();
+    cycles.dedup();
+
+    let (mut dependencies, mut missing): (Vec<String>, Vec<String>) = depths.into_keys()
+        .filter(|&name| name != root)
+        .map(str::to_owned)
+        .partition(|name| dependencies_of.contains_key(name));
+    dependencies.sort();
+    missing.sort();
+
+    Ok(DependencyTree { root: root.to_owned(), dependencies, missing, install_order, cycles })
+}
+
+fn rotate_to_smallest(mut cycle: Vec<String>) -> Vec<String> {
+    if let Some(smallest) = cycle.iter().enumerate().min_by(|a, b| a.1.cmp(b.1)).map(|(idx, _)| idx) {
+        cycle.rotate_left(smallest);
+    }
+    cycle
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::HashMap;
+
+    use anyhow::{Result, Ok};
+
+    use super::resolve;
+
+    fn graph(edges: &[(&str, &[&str])]) -> HashMap<String, Vec<String>> {
+        edges.iter()
+            .map(|(name, deps)| (name.to_string(), deps.iter().map(|dep| dep.to_string()).collect()))
+            .collect()
+    }
+
+    #[test]
+    fn test_resolve() -> Result<()> {
+        let graph = graph(&[
+            ("app", &["lib", "cli", "gone"]),
+            ("cli", &["lib"]),
+            ("lib", &["core"]),
+            ("core", &["util"]),
+            ("util", &["core"]),
+        ]);
+
+        let tree = resolve("app", None, &graph)?;
+        assert_eq!(tree.dependencies, vec!["cli", "core", "lib", "util"]);
+        assert_eq!(tree.missing, vec!["gone"]);
+        assert_eq!(tree.install_order, vec!["util", "core", "lib", "cli", "app"]);
+        assert_eq!(tree.cycles, vec![vec!["core", "util"]]);
+
+        let tree = resolve("app", Some(1), &graph)?;
+        assert_eq!(tree.dependencies, vec!["cli", "lib"]);
+        assert_eq!(tree.install_order, vec!["cli", "lib", "app"]);
+        assert!(tree.cycles.is_empty());
+
+        assert!(resolve("gone", None, &graph).is_err());
+        Ok(())
+    }
+}
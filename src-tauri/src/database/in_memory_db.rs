@@ -0,0 +1,337 @@
+use std::{time::{Instant, Duration}, collections::{HashMap, HashSet, BTreeMap}, sync::{Arc, Mutex, MutexGuard, OnceLock}};
+
+use anyhow::{Result, Ok, anyhow, bail};
+use async_trait::async_trait;
+
+use crate::{models::{BasicPackageData, BasicPackagePatch, CatalogStats, DependencyTree, PackageData}, config::ConnectionProfile};
+
+use super::{DbActions, DbResponse, Connect, PhaseTimer, sort_values_by, dependency_graph, search, stats::Aggregates};
+
+const DEFAULT_STORE: &str = "default";
+
+// Packages are kept ordered by name, so ties in sorting are resolved the same way on every run
+type Catalog = BTreeMap<String, PackageData>;
+
+// Named stores live for the whole process, so reconnecting does not lose the data
+static STORES: OnceLock<Mutex<HashMap<String, Arc<Mutex<Catalog>>>>> = OnceLock::new();
+
+/// Reference implementation of `DbActions` on plain Rust collections.
+/// Used as the oracle for the real backends and as a backend that works without any database server.
+/// Without a database all of the time is reported as client side processing.
+pub struct InMemoryDb {
+    catalog: Arc<Mutex<Catalog>>
+}
+
+impl InMemoryDb {
+    /// Creates an empty store that is not shared with any other client.
+    pub fn new() -> Self {
+        Self { catalog: Arc::new(Mutex::new(Catalog::new())) }
+    }
+
+    /// Opens the store named by `profile.database`, shared by every client using the same name.
+    pub fn try_new(profile: &ConnectionProfile) -> Result<Self> {
+        let name = profile.database.as_deref().unwrap_or(DEFAULT_STORE);
+        let mut stores = STORES
+            .get_or_init(|| Mutex::new(HashMap::new()))
+            .lock()
+            .map_err(|_| anyhow!("In-memory stores are poisoned"))?;
+        let catalog = stores.entry(name.to_owned()).or_default().clone();
+        Ok(Self { catalog })
+    }
+
+    fn catalog(&self) -> Result<MutexGuard<'_, Catalog>> {
+        self.catalog.lock().map_err(|_| anyhow!("In-memory catalog is poisoned"))
+    }
+
+    fn sorted_basic_package_data(&self, field: &str) -> Result<Vec<BasicPackageData>> {
+        let mut packages: Vec<BasicPackageData> = self.catalog()?
+            .values()
+            .map(|pkg| pkg.basic.clone())
+            .collect();
+        sort_values_by(&mut packages, field)?;
+        Ok(packages)
+    }
+
+    // Supported queries: `KEYS`, `COUNT` and `GET <name>`
+    fn execute(&self, query: &str) -> Result<String> {
+        let parts: Vec<&str> = query.split_whitespace().collect();
+        let catalog = self.catalog()?;
+        let result = match parts.as_slice() {
+            [cmd] if cmd.eq_ignore_ascii_case("KEYS") => serde_json::to_string(&catalog.keys().collect::<Vec<_>>())?,
+            [cmd] if cmd.eq_ignore_ascii_case("COUNT") => catalog.len().to_string(),
+            [cmd, name] if cmd.eq_ignore_ascii_case("GET") => match catalog.get(*name) {
+                Some(pkg) => serde_json::to_string(pkg)?,
+                None => "No data found".to_owned(),
+            },
+            _ => bail!("Unsupported query, expected one of: KEYS, COUNT, GET <name>"),
+        };
+        Ok(result)
+    }
+}
+
+impl Default for InMemoryDb {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[async_trait]
+impl Connect for InMemoryDb {
+    async fn connect(profile: &ConnectionProfile) -> Result<Self> {
+        Self::try_new(profile)
+    }
+}
+
+#[async_trait]
+impl DbActions for InMemoryDb {
+    async fn get_custom_query_time(&mut self, query: &str) -> Result<Duration> {
+        let start = Instant::now();
+        self.execute(query)?;
+        Ok(start.elapsed())
+    }
+
+    async fn run_custom_query(&mut self, query: &str) -> Result<DbResponse<String>> {
+        let timer = PhaseTimer::start();
+        let result = self.execute(query)?;
+        Ok(timer.respond(result))
+    }
+
+    async fn sort_pkgs_by_field_with_limit(&mut self, field: &str, limit_start: u32, limit_end: u32) -> Result<DbResponse<Vec<String>>> {
+        let timer = PhaseTimer::start();
+        let packages = self.sorted_basic_package_data(field)?;
+
+        let result: Vec<String> = packages.into_iter().rev()
+            .skip(limit_start as usize)
+            .take(limit_end.saturating_sub(limit_start) as usize)
+            .map(|v| v.name)
+            .collect();
+        Ok(timer.respond(result))
+    }
+
+    async fn get_most_voted_pkgs(&mut self, number: u32) -> Result<DbResponse<Vec<BasicPackageData>>> {
+        let timer = PhaseTimer::start();
+        let packages = self.sorted_basic_package_data("votes")?;
+
+        let result: Vec<BasicPackageData> = packages.into_iter().rev()
+            .take(number as usize)
+            .collect();
+        Ok(timer.respond(result))
+    }
+
+    async fn insert_pkg(&mut self, pkg: &PackageData) -> Result<DbResponse<()>> {
+        let timer = PhaseTimer::start();
+        self.catalog()?.insert(pkg.basic.name.clone(), pkg.clone());
+        Ok(timer.respond(()))
+    }
+
+    async fn insert_pkgs(&mut self, pkgs: &[PackageData]) -> Result<DbResponse<()>> {
+        let timer = PhaseTimer::start();
+        let mut catalog = self.catalog()?;
+        for pkg in pkgs {
+            catalog.insert(pkg.basic.name.clone(), pkg.clone());
+        }
+        Ok(timer.respond(()))
+    }
+
+    async fn get_pkg(&mut self, name: &str) -> Result<DbResponse<PackageData>> {
+        let timer = PhaseTimer::start();
+        let result = self.catalog()?
+            .get(name)
+            .cloned()
+            .ok_or(anyhow!("Package {} not found", name))?;
+        Ok(timer.respond(result))
+    }
+
+    async fn contains_pkg(&mut self, pkg_name: &str) -> Result<DbResponse<bool>> {
+        let timer = PhaseTimer::start();
+        let result = self.catalog()?.contains_key(pkg_name);
+        Ok(timer.respond(result))
+    }
+
+    async fn remove_comments(&mut self, pkg_name: &str) -> Result<DbResponse<()>> {
+        let timer = PhaseTimer::start();
+        if let Some(pkg) = self.catalog()?.get_mut(pkg_name) {
+            pkg.comments.clear();
+        }
+        Ok(timer.respond(()))
+    }
+
+    async fn delete_pkg(&mut self, pkg_name: &str) -> Result<DbResponse<()>> {
+        let timer = PhaseTimer::start();
+        self.catalog()?.remove(pkg_name);
+        Ok(timer.respond(()))
+    }
+
+    async fn get_reverse_dependencies(&mut self, pkg_name: &str, groups: &[String]) -> Result<DbResponse<Vec<String>>> {
+        let timer = PhaseTimer::start();
+        // The catalog is ordered by name, so the result comes out sorted
+        let result: Vec<String> = self.catalog()?
+            .values()
+            .filter(|pkg| pkg.dependencies.iter().any(|dep| {
+                (groups.is_empty() || groups.contains(&dep.group)) && dep.packages.iter().any(|name| name == pkg_name)
+            }))
+            .map(|pkg| pkg.basic.name.clone())
+            .collect();
+        Ok(timer.respond(result))
+    }
+
+    async fn resolve_dependency_tree(&mut self, pkg_name: &str, groups: &[String], max_depth: Option<u32>) -> Result<DbResponse<DependencyTree>> {
+        let timer = PhaseTimer::start();
+        // The whole catalog is at hand, the resolver only follows what is reachable
+        let dependencies_of: HashMap<String, Vec<String>> = self.catalog()?
+            .values()
+            .map(|pkg| (pkg.basic.name.clone(), dependency_graph::dependency_names(pkg, groups)))
+            .collect();
+        let result = dependency_graph::resolve(pkg_name, max_depth, &dependencies_of)?;
+        Ok(timer.respond(result))
+    }
+
+    async fn search_pkgs(&mut self, query: &str, limit: u32) -> Result<DbResponse<Vec<String>>> {
+        let words = search::query_words(query)?;
+        let timer = PhaseTimer::start();
+        let hits: Vec<(String, f32)> = self.catalog()?
+            .values()
+            .filter(|pkg| search::matches(&search::pkg_words(&pkg.basic.name, &pkg.basic.description, pkg.additional.keywords.as_deref()), &words))
+            .map(|pkg| (pkg.basic.name.clone(), pkg.basic.popularity))
+            .collect();
+        let result = search::rank(hits, limit);
+        Ok(timer.respond(result))
+    }
+
+    async fn get_pkgs_by_maintainer(&mut self, maintainer: &str) -> Result<DbResponse<Vec<String>>> {
+        let timer = PhaseTimer::start();
+        let result: Vec<String> = self.catalog()?
+            .values()
+            .filter(|pkg| pkg.basic.maintainer == maintainer)
+            .map(|pkg| pkg.basic.name.clone())
+            .collect();
+        Ok(timer.respond(result))
+    }
+
+    async fn get_pkgs_by_submitter(&mut self, submitter: &str) -> Result<DbResponse<Vec<String>>> {
+        let timer = PhaseTimer::start();
+        let result: Vec<String> = self.catalog()?
+            .values()
+            .filter(|pkg| pkg.additional.submitter == submitter)
+            .map(|pkg| pkg.basic.name.clone())
+            .collect();
+        Ok(timer.respond(result))
+    }
+
+    async fn get_catalog_stats(&mut self) -> Result<DbResponse<CatalogStats>> {
+        let timer = PhaseTimer::start();
+        let mut aggregates = Aggregates::default();
+        for pkg in self.catalog()?.values() {
+            aggregates.add_pkg(
+                pkg.basic.votes,
+                pkg.basic.popularity,
+                pkg.additional.license.as_deref(),
+                &pkg.basic.maintainer,
+                pkg.comments.len() as u64,
+            );
+        }
+        let result = aggregates.into_stats();
+        Ok(timer.respond(result))
+    }
+
+    async fn update_pkg_fields(&mut self, pkg_name: &str, patch: &BasicPackagePatch) -> Result<DbResponse<()>> {
+        patch.ensure_not_empty()?;
+        let timer = PhaseTimer::start();
+        let mut catalog = self.catalog()?;
+        let pkg = catalog.get_mut(pkg_name).ok_or(anyhow!("Package {} not found", pkg_name))?;
+        patch.apply(&mut pkg.basic);
+        Ok(timer.respond(()))
+    }
+
+    async fn increment_votes(&mut self, pkg_name: &str, delta: i32) -> Result<DbResponse<i32>> {
+        let timer = PhaseTimer::start();
+        let mut catalog = self.catalog()?;
+        let pkg = catalog.get_mut(pkg_name).ok_or(anyhow!("Package {} not found", pkg_name))?;
+        pkg.basic.votes = match pkg.basic.votes.checked_add(delta) {
+            Some(votes) => votes,
+            None => bail!("Votes of package {} would overflow", pkg_name),
+        };
+        let result = pkg.basic.votes;
+        Ok(timer.respond(result))
+    }
+
+    async fn get_packages_occurences_in_deps(&mut self, pkg_names: &[String], groups: &[String]) -> Result<DbResponse<HashMap<String, u32>>> {
+        let mut result: HashMap<String, u32> = HashMap::new();
+        pkg_names.iter().for_each(|name| _ = result.insert(name.to_owned(), 0));
+
+        let timer = PhaseTimer::start();
+        for pkg in self.catalog()?.values() {
+            // A name repeated within the same group of a package is counted once
+            let listed: HashSet<(&str, &str)> = pkg.dependencies.iter()
+                .filter(|dep| groups.is_empty() || groups.contains(&dep.group))
+                .flat_map(|dep| dep.packages.iter().map(|name| (dep.group.as_str(), name.as_str())))
+                .collect();
+            for (_, name) in listed {
+                if let Some(count) = result.get_mut(name) {
+                    *count += 1;
+                }
+            }
+        }
+        Ok(timer.respond(result))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::InMemoryDb;
+    use anyhow::{Result, Ok};
+    use super::DbActions;
+    use crate::database::conformance::fixture_pkgs;
+
+    #[tokio::test]
+    async fn test_query() -> Result<()> {
+        let mut db = InMemoryDb::new();
+        for pkg in fixture_pkgs() {
+            db.insert_pkg(&pkg).await?;
+        }
+
+        let result = db.sort_pkgs_by_field_with_limit("votes", 1, 3).await?;
+        assert_eq!(result.result, vec!["pkg-3", "pkg-2"]);
+
+        let result = db.get_most_voted_pkgs(2).await?;
+        let names: Vec<&str> = result.result.iter().map(|pkg| pkg.name.as_str()).collect();
+        assert_eq!(names, vec!["pkg-4", "pkg-3"]);
+
+        db.remove_comments("pkg-1").await?;
+        assert!(db.get_pkg("pkg-1").await?.result.comments.is_empty());
+        assert!(db.get_pkg("missing").await.is_err());
+
+        let result = db.get_reverse_dependencies("pkg-2", &[]).await?;
+        assert_eq!(result.result, vec!["pkg-3"]);
+        assert!(db.get_reverse_dependencies("pkg-2", &["optdepends".to_string()]).await?.result.is_empty());
+
+        let result = db.resolve_dependency_tree("pkg-3", &[], None).await?;
+        assert_eq!(result.result.install_order, vec!["pkg-0", "pkg-1", "pkg-2", "pkg-3"]);
+        assert_eq!(db.resolve_dependency_tree("pkg-3", &[], Some(1)).await?.result.dependencies, vec!["pkg-2"]);
+
+        assert_eq!(db.search_pkgs("fixture pack", 2).await?.result, vec!["pkg-4", "pkg-3"]);
+        assert_eq!(db.search_pkgs("PKG-1", 10).await?.result, vec!["pkg-1"]);
+        assert!(db.search_pkgs("ixture", 10).await?.result.is_empty());
+        assert!(db.search_pkgs(" - ", 10).await.is_err());
+
+        let votes = db.get_pkg("pkg-4").await?.result.basic.votes;
+        assert!(db.increment_votes("pkg-4", i32::MAX).await.is_err());
+        assert_eq!(db.get_pkg("pkg-4").await?.result.basic.votes, votes);
+
+        let stats = db.get_catalog_stats().await?.result;
+        assert_eq!(stats.pkg_count, 5);
+        assert_eq!(stats.average_votes, 20.0);
+        let histogram: Vec<u64> = stats.popularity_histogram.iter().map(|bucket| bucket.count).collect();
+        assert_eq!(histogram, vec![1, 1, 3, 0, 0]);
+        assert_eq!(stats.top_maintainers[0].name, "maintainer-0");
+        assert_eq!(stats.comment_counts.len(), 2);
+
+        let names = vec!["pkg-0".to_string(), "pkg-4".to_string()];
+        let result = db.get_packages_occurences_in_deps(&names, &[]).await?;
+        assert_eq!(result.result["pkg-0"], 1);
+        assert_eq!(result.result["pkg-4"], 0);
+        let result = db.get_packages_occurences_in_deps(&names, &["makedepends".to_string()]).await?;
+        assert_eq!(result.result["pkg-0"], 0);
+        Ok(())
+    }
+}
@@ -1,7 +1,7 @@
-use std::{time::Duration, collections::HashMap};
+use std::{time::Duration, collections::{HashMap, HashSet}, cmp::Ordering::Equal};
 use async_trait::async_trait;
 use serde::{Serialize, Deserialize};
-use anyhow::Result;
+use anyhow::{Result, bail};
 
 mod redis_db;
 pub use redis_db::RedisDb;
@@ -12,12 +12,43 @@ pub use skytable_db::SkytableClient;
 mod surreal_db;
 pub use surreal_db::SurrealDbClient;
 
-use crate::models::{BasicPackageData, PackageData};
+mod sqlite_db;
+pub use sqlite_db::SqliteDb;
+
+mod in_memory_db;
+pub use in_memory_db::InMemoryDb;
+
+mod registry;
+pub use registry::{BackendRegistry, Connect};
+
+mod operation;
+pub use operation::Operation;
+
+mod dependency_graph;
+
+mod search;
+
+mod stats;
+
+mod timing;
+pub use timing::Timings;
+use timing::PhaseTimer;
+
+use crate::models::{BasicPackageData, BasicPackagePatch, CatalogStats, DependencyTree, PackageData};
 
 #[derive(Serialize, Deserialize, Debug)]
 pub struct DbResponse<T: Serialize> {
     pub result: T,
-    pub duration: Duration
+    pub duration: Duration,
+    #[serde(default)]
+    pub timings: Timings,
+}
+
+impl<T: Serialize> DbResponse<T> {
+    /// Response of an operation answered without doing any work, e.g. for an empty input.
+    pub fn untimed(result: T) -> Self {
+        Self { result, duration: Duration::ZERO, timings: Timings::default() }
+    }
 }
 
 #[async_trait]
@@ -26,8 +57,106 @@ pub trait DbActions {
     async fn run_custom_query(&mut self, query: &str) -> Result<DbResponse<String>>;
     async fn sort_pkgs_by_field_with_limit(&mut self, field: &str, limit_start: u32, limit_end: u32) -> Result<DbResponse<Vec<String>>>;
     async fn get_most_voted_pkgs(&mut self, number: u32) -> Result<DbResponse<Vec<BasicPackageData>>>;
+    /// Writes the package, an already stored package of the same name is replaced with all data it owns.
     async fn insert_pkg(&mut self, pkg: &PackageData) -> Result<DbResponse<()>>;
+    /// Inserts all packages using the backend's native batching, the duration covers the whole batch.
+    /// Stored packages are replaced like in `insert_pkg`, a package listed twice ends up with its last data.
+    async fn insert_pkgs(&mut self, pkgs: &[PackageData]) -> Result<DbResponse<()>>;
     async fn get_pkg(&mut self, pkg_name: &str) -> Result<DbResponse<PackageData>>;
+    /// Whether the package exists, which tells a missing package apart from a failed `get_pkg`.
+    async fn contains_pkg(&mut self, pkg_name: &str) -> Result<DbResponse<bool>>;
     async fn remove_comments(&mut self, pkg_name: &str) -> Result<DbResponse<()>>;
-    async fn get_packages_occurences_in_deps(&mut self, pkg_names: &Vec<String>) -> Result<DbResponse<HashMap<String, u32>>>;
+    /// Removes the package with all data it owns, deleting a missing package is not an error.
+    async fn delete_pkg(&mut self, pkg_name: &str) -> Result<DbResponse<()>>;
+    /// Names of the packages listing `pkg_name` in one of the dependency `groups` (any group when empty), sorted by name.
+    async fn get_reverse_dependencies(&mut self, pkg_name: &str, groups: &[String]) -> Result<DbResponse<Vec<String>>>;
+    /// Names of the packages having every word of `query` as a prefix of a word in their name, description or keywords,
+    /// most popular first; see `search` for the exact matching rules.
+    async fn search_pkgs(&mut self, query: &str, limit: u32) -> Result<DbResponse<Vec<String>>>;
+    /// Names of the packages maintained by `maintainer`, sorted by name.
+    async fn get_pkgs_by_maintainer(&mut self, maintainer: &str) -> Result<DbResponse<Vec<String>>>;
+    /// Names of the packages submitted by `submitter`, sorted by name.
+    async fn get_pkgs_by_submitter(&mut self, submitter: &str) -> Result<DbResponse<Vec<String>>>;
+    /// Package count, votes, popularity histogram, most common licenses and maintainers and the distribution of comment counts.
+    async fn get_catalog_stats(&mut self) -> Result<DbResponse<CatalogStats>>;
+    /// Changes only the fields set in the patch, fails if the package does not exist.
+    async fn update_pkg_fields(&mut self, pkg_name: &str, patch: &BasicPackagePatch) -> Result<DbResponse<()>>;
+    /// Adds `delta` to the votes of the package and returns the new number of votes.
+    async fn increment_votes(&mut self, pkg_name: &str, delta: i32) -> Result<DbResponse<i32>>;
+    /// Counts, for each of `pkg_names`, the packages listing it in one of the dependency `groups` (any group when empty).
+    /// A package listing the name in several groups counts once per group; names listed nowhere count zero.
+    async fn get_packages_occurences_in_deps(&mut self, pkg_names: &[String], groups: &[String]) -> Result<DbResponse<HashMap<String, u32>>>;
+
+    /// Transitive dependencies of the package through the dependency `groups` (any group when empty), up to `max_depth` levels.
+    /// This default walks the graph in Rust, one `get_pkg` per package; dependencies which do not exist are reported as missing,
+    /// any other failure to fetch one fails the whole resolution.
+    async fn resolve_dependency_tree(&mut self, pkg_name: &str, groups: &[String], max_depth: Option<u32>) -> Result<DbResponse<DependencyTree>> {
+        let mut timer = PhaseTimer::start();
+        let root = self.get_pkg(pkg_name).await?;
+        timer.nested(&root.timings);
+        let root = root.result;
+        let mut frontier = dependency_graph::dependency_names(&root, groups);
+        let mut dependencies_of = HashMap::from([(pkg_name.to_owned(), frontier.clone())]);
+        let mut fetched = HashSet::from([pkg_name.to_owned()]);
+        let mut depth = 1;
+        // Packages at `max_depth` are fetched too, only to tell them apart from missing ones
+        while !frontier.is_empty() && depth <= max_depth.unwrap_or(u32::MAX) {
+            let mut next = vec![];
+            for name in frontier {
+                if !fetched.insert(name.clone()) {
+                    continue;
+                }
+                match self.get_pkg(&name).await {
+                    Ok(pkg) => {
+                        timer.nested(&pkg.timings);
+                        let deps = dependency_graph::dependency_names(&pkg.result, groups);
+                        next.extend(deps.iter().cloned());
+                        dependencies_of.insert(name, deps);
+                    },
+                    Err(error) => {
+                        let exists = self.contains_pkg(&name).await?;
+                        timer.nested(&exists.timings);
+                        if exists.result {
+                            return Err(error);
+                        }
+                    },
+                }
+            }
+            frontier = next;
+            depth += 1;
+        }
+        let result = dependency_graph::resolve(pkg_name, max_depth, &dependencies_of)?;
+        Ok(timer.respond(result))
+    }
+
+    /// Lists keys left behind by packages which no longer own them, deleting them when `remove` is set.
+    /// Backends which keep a package in a single record or enforce ownership themselves have nothing to report.
+    async fn find_orphaned_keys(&mut self, _remove: bool) -> Result<DbResponse<Vec<String>>> {
+        Ok(DbResponse::untimed(vec![]))
+    }
+
+    /// Whether a failed operation left the connection unusable, so that the registry connects again for the next one.
+    /// Errors of the operation itself, e.g. a missing package or an unsupported field, keep the connection.
+    fn is_connection_error(&self, error: &anyhow::Error) -> bool {
+        error.chain().any(|cause| cause.is::<std::io::Error>())
+    }
+}
+
+/// Sorts packages ascending by the given `BasicPackageData` field, for backends that sort on the client side.
+fn sort_values_by(data: &mut [BasicPackageData], key: &str) -> Result<()> {
+    match key {
+       "name" => data.sort_by_key(|k| k.name.clone()),
+       "version" => data.sort_by_key(|k| k.version.clone()),
+       "path_to_additional_data" => data.sort_by_key(|k| k.path_to_additional_data.clone()),
+       "votes" => data.sort_by_key(|k| k.votes),
+       "popularity" => data.sort_by(|a, b| a.popularity.partial_cmp(&b.popularity).unwrap_or(Equal)),
+       "description" => data.sort_by_key(|k| k.description.clone()),
+       "maintainer" => data.sort_by_key(|k| k.maintainer.clone()),
+       "last_updated" => data.sort_by_key(|k| k.last_updated.clone()),
+       _ => bail!("Unsuported field")
+    }
+    Ok(())
 }
+
+#[cfg(test)]
+pub mod conformance;
@@ -0,0 +1,86 @@
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::models::{PackageData, BasicPackagePatch};
+
+use super::{DbActions, DbResponse};
+
+/// A single `DbActions` call together with its arguments, so it can be repeated or sent to several backends.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(tag = "name", content = "args", rename_all = "snake_case")]
+pub enum Operation {
+    RunCustomQuery { query: String },
+    SortPkgsByFieldWithLimit { field: String, limit_start: u32, limit_end: u32 },
+    GetMostVotedPkgs { number: u32 },
+    InsertPkg { pkg: Box<PackageData> },
+    InsertPkgs { pkgs: Vec<PackageData> },
+    GetPkg { name: String },
+    RemoveComments { pkg_name: String },
+    DeletePkg { pkg_name: String },
+    GetReverseDependencies { pkg_name: String, groups: Vec<String> },
+    ResolveDependencyTree { pkg_name: String, groups: Vec<String>, max_depth: Option<u32> },
+    SearchPkgs { query: String, limit: u32 },
+    GetPkgsByMaintainer { maintainer: String },
+    GetPkgsBySubmitter { submitter: String },
+    GetCatalogStats,
+    UpdatePkgFields { pkg_name: String, patch: BasicPackagePatch },
+    IncrementVotes { pkg_name: String, delta: i32 },
+    GetPackagesOccurencesInDeps {
+        pkg_names: Vec<String>,
+        #[serde(default)]
+        groups: Vec<String>,
+    },
+    FindOrphanedKeys { remove: bool },
+}
+
+impl Operation {
+    pub async fn run(&self, db: &mut (dyn DbActions + Send)) -> Result<DbResponse<Value>> {
+        let response = match self {
+            Operation::RunCustomQuery { query } => into_value(db.run_custom_query(query).await?)?,
+            Operation::SortPkgsByFieldWithLimit { field, limit_start, limit_end } =>
+                into_value(db.sort_pkgs_by_field_with_limit(field, *limit_start, *limit_end).await?)?,
+            Operation::GetMostVotedPkgs { number } => into_value(db.get_most_voted_pkgs(*number).await?)?,
+            Operation::InsertPkg { pkg } => into_value(db.insert_pkg(pkg).await?)?,
+            Operation::InsertPkgs { pkgs } => into_value(db.insert_pkgs(pkgs).await?)?,
+            Operation::GetPkg { name } => into_value(db.get_pkg(name).await?)?,
+            Operation::RemoveComments { pkg_name } => into_value(db.remove_comments(pkg_name).await?)?,
+            Operation::DeletePkg { pkg_name } => into_value(db.delete_pkg(pkg_name).await?)?,
+            Operation::GetReverseDependencies { pkg_name, groups } => into_value(db.get_reverse_dependencies(pkg_name, groups).await?)?,
+            Operation::ResolveDependencyTree { pkg_name, groups, max_depth } =>
+                into_value(db.resolve_dependency_tree(pkg_name, groups, *max_depth).await?)?,
+            Operation::SearchPkgs { query, limit } => into_value(db.search_pkgs(query, *limit).await?)?,
+            Operation::GetPkgsByMaintainer { maintainer } => into_value(db.get_pkgs_by_maintainer(maintainer).await?)?,
+            Operation::GetPkgsBySubmitter { submitter } => into_value(db.get_pkgs_by_submitter(submitter).await?)?,
+            Operation::GetCatalogStats => into_value(db.get_catalog_stats().await?)?,
+            Operation::UpdatePkgFields { pkg_name, patch } => into_value(db.update_pkg_fields(pkg_name, patch).await?)?,
+            Operation::IncrementVotes { pkg_name, delta } => into_value(db.increment_votes(pkg_name, *delta).await?)?,
+            Operation::GetPackagesOccurencesInDeps { pkg_names, groups } =>
+                into_value(db.get_packages_occurences_in_deps(pkg_names, groups).await?)?,
+            Operation::FindOrphanedKeys { remove } => into_value(db.find_orphaned_keys(*remove).await?)?,
+        };
+        Ok(response)
+    }
+
+    /// Brings a result to a form that can be compared across backends, `None` if results are backend specific.
+    pub fn canonical_result(&self, mut result: Value) -> Option<Value> {
+        match self {
+            // Custom queries and key names are backend specific
+            Operation::RunCustomQuery { .. } | Operation::FindOrphanedKeys { .. } => None,
+            // Comments and dependency groups are stored in sets by some backends
+            Operation::GetPkg { .. } => {
+                for key in ["comments", "dependencies"] {
+                    if let Some(Value::Array(items)) = result.get_mut(key) {
+                        items.sort_by_key(|item| item.to_string());
+                    }
+                }
+                Some(result)
+            },
+            _ => Some(result),
+        }
+    }
+}
+
+fn into_value<T: Serialize>(response: DbResponse<T>) -> Result<DbResponse<Value>> {
+    Ok(DbResponse { result: serde_json::to_value(response.result)?, duration: response.duration, timings: response.timings })
+}
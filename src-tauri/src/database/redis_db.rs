@@ -1,27 +1,372 @@
-use std::{time::{Instant, Duration}, collections::HashMap};
+use std::{time::{Instant, Duration}, collections::{HashMap, HashSet}};
 
-use redis::{Client, Commands};
+use redis::{Client, AsyncCommands, ConnectionInfo, ErrorKind, FromRedisValue, IntoConnectionInfo, Script, aio::ConnectionManager};
 use anyhow::{Result, Ok, anyhow};
 use tauri::regex::internal::Inst;
-use crate::models::{BasicPackageData, PackageData, Comment, PackageDependency};
+use crate::{models::{BasicPackageData, BasicPackagePatch, CatalogStats, PackageData, Comment, PackageDependency}, config::{ConnectionProfile, RedisSortStrategy}};
 
-use super::{DbActions, DbResponse};
+use super::{DbActions, DbResponse, Connect, PhaseTimer, search, stats::Aggregates};
 use async_trait::async_trait;
 
+// Deletes the comment set given as KEYS[1] together with every comment hash it lists
+const REMOVE_COMMENTS_SCRIPT: &str = "
+    local comments = redis.call('SMEMBERS', KEYS[1])
+    for _, comment in ipairs(comments) do
+        redis.call('DEL', comment)
+    end
+    redis.call('DEL', KEYS[1])
+    return #comments
+";
+
+// Deletes every key of the package `KEYS[1]` named `ARGV[1]` and drops it from the reverse dependency sets
+// (`pkgs:{dep}:rdeps:{group}`) of its dependencies, from the sets of its maintainer and submitter and from
+// the sorted set indexes; the sets of packages depending on it are kept
+const DELETE_PKG_SCRIPT: &str = "
+    local name = ARGV[1]
+    local last_updated = redis.call('HGET', KEYS[1], 'last_updated')
+    if last_updated then
+        redis.call('ZREM', 'pkgs_by:last_updated', last_updated .. '\\0' .. name)
+    end
+    redis.call('ZREM', 'pkgs_by:votes', name)
+    redis.call('ZREM', 'pkgs_by:popularity', name)
+    for _, owner in ipairs({'maintainer', 'submitter'}) do
+        local value = redis.call('HGET', KEYS[1], owner)
+        if value then
+            redis.call('SREM', owner .. 's:' .. value, name)
+        end
+    end
+    local comments_key = KEYS[1] .. ':cmnts'
+    local deps_key = KEYS[1] .. ':deps'
+    for _, comment in ipairs(redis.call('SMEMBERS', comments_key)) do
+        redis.call('DEL', comment)
+    end
+    for _, group_key in ipairs(redis.call('SMEMBERS', deps_key)) do
+        local group = string.sub(group_key, #deps_key + 2)
+        for _, dep in ipairs(redis.call('LRANGE', group_key, 0, -1)) do
+            local rdeps_key = 'pkgs:' .. dep .. ':rdeps'
+            local rdeps_group_key = rdeps_key .. ':' .. group
+            redis.call('SREM', rdeps_group_key, name)
+            if redis.call('SCARD', rdeps_group_key) == 0 then
+                redis.call('SREM', rdeps_key, rdeps_group_key)
+            end
+        end
+        redis.call('DEL', group_key)
+    end
+    local words_key = KEYS[1] .. ':words'
+    for _, word in ipairs(redis.call('SMEMBERS', words_key)) do
+        redis.call('SREM', 'search:word:' .. word, name)
+        if redis.call('EXISTS', 'search:word:' .. word) == 0 then
+            redis.call('ZREM', 'search:words', word)
+        end
+    end
+    redis.call('DEL', KEYS[1], comments_key, deps_key, words_key)
+    redis.call('SREM', 'pkgs_set', name)
+    redis.call('SREM', 'pkgs_indexed:rdeps', name)
+    redis.call('SREM', 'pkgs_indexed:words', name)
+";
+
+// Replaces the search words (`ARGV[2..]`) of the package `KEYS[1]` named `ARGV[1]`. Every word has the set of
+// packages having it (`search:word:{word}`) and is kept in the sorted set `search:words` for prefix lookups;
+// the package is added to `pkgs_indexed:words`
+const INDEX_WORDS_SCRIPT: &str = "
+    local name = ARGV[1]
+    local words_key = KEYS[1] .. ':words'
+    for _, word in ipairs(redis.call('SMEMBERS', words_key)) do
+        redis.call('SREM', 'search:word:' .. word, name)
+        if redis.call('EXISTS', 'search:word:' .. word) == 0 then
+            redis.call('ZREM', 'search:words', word)
+        end
+    end
+    redis.call('DEL', words_key)
+    for idx = 2, #ARGV do
+        redis.call('SADD', 'search:word:' .. ARGV[idx], name)
+        redis.call('ZADD', 'search:words', 0, ARGV[idx])
+        redis.call('SADD', words_key, ARGV[idx])
+    end
+    redis.call('SADD', 'pkgs_indexed:words', name)
+";
+
+// Sums the sizes of the sets `KEYS`, missing keys count as empty
+const SUM_CARDINALITIES_SCRIPT: &str = "
+    local count = 0
+    for _, key in ipairs(KEYS) do
+        count = count + redis.call('SCARD', key)
+    end
+    return count
+";
+
+// Plain HSET and HINCRBY would create a partial hash for a package which does not exist.
+// A new maintainer moves the package `KEYS[1]` between the `maintainers:{name}` sets, packages inserted
+// with the sorted set strategy (those in `pkgs_by:votes`) get their index entries rewritten
+const UPDATE_SCRIPT: &str = "
+    if redis.call('EXISTS', KEYS[1]) == 0 then
+        return redis.error_reply('Package not found')
+    end
+    local name = string.sub(KEYS[1], #'pkgs:' + 1)
+    local old = redis.call('HMGET', KEYS[1], 'maintainer', 'last_updated')
+    local changed = redis.call('HSET', KEYS[1], unpack(ARGV))
+    local new = redis.call('HMGET', KEYS[1], 'maintainer', 'last_updated', 'votes', 'popularity')
+    if old[1] ~= new[1] then
+        redis.call('SREM', 'maintainers:' .. old[1], name)
+        redis.call('SADD', 'maintainers:' .. new[1], name)
+    end
+    if redis.call('ZSCORE', 'pkgs_by:votes', name) then
+        redis.call('ZREM', 'pkgs_by:last_updated', old[2] .. '\\0' .. name)
+        redis.call('ZADD', 'pkgs_by:last_updated', 0, new[2] .. '\\0' .. name)
+        redis.call('ZADD', 'pkgs_by:votes', new[3], name)
+        redis.call('ZADD', 'pkgs_by:popularity', new[4], name)
+    end
+    return changed
+";
+
+const INCREMENT_VOTES_SCRIPT: &str = "
+    if redis.call('EXISTS', KEYS[1]) == 0 then
+        return redis.error_reply('Package not found')
+    end
+    local votes = tonumber(redis.call('HGET', KEYS[1], 'votes') or 0) + tonumber(ARGV[1])
+    if votes < -2147483648 or votes > 2147483647 then
+        return redis.error_reply('Votes of package would overflow')
+    end
+    redis.call('HSET', KEYS[1], 'votes', votes)
+    local name = string.sub(KEYS[1], #'pkgs:' + 1)
+    if redis.call('ZSCORE', 'pkgs_by:votes', name) then
+        redis.call('ZADD', 'pkgs_by:votes', votes, name)
+    end
+    return votes
+";
+
+// Rebuilds the sorted set indexes from the package hashes when they do not cover every package of `pkgs_set`,
+// e.g. after the scraper or a `Sort` profile wrote packages. Runs as one script, so it blocks the server for a full
+// pass over the catalog, but only once per change of the package count made outside of the `SortedSet` strategy
+const ENSURE_SORT_INDEXES_SCRIPT: &str = "
+    local count = redis.call('SCARD', 'pkgs_set')
+    if redis.call('ZCARD', 'pkgs_by:votes') == count then
+        return 0
+    end
+    redis.call('DEL', 'pkgs_by:votes', 'pkgs_by:popularity', 'pkgs_by:last_updated')
+    for _, name in ipairs(redis.call('SMEMBERS', 'pkgs_set')) do
+        local fields = redis.call('HMGET', 'pkgs:' .. name, 'votes', 'popularity', 'last_updated')
+        redis.call('ZADD', 'pkgs_by:votes', fields[1] or 0, name)
+        redis.call('ZADD', 'pkgs_by:popularity', fields[2] or 0, name)
+        redis.call('ZADD', 'pkgs_by:last_updated', 0, (fields[3] or '') .. '\\0' .. name)
+    end
+    return count
+";
+
+// Adds the packages of `pkgs_set` missing from `pkgs_indexed:rdeps`, e.g. written by the scraper, to the reverse
+// dependency sets of their dependencies. Like the sorted set rebuild it only does a full pass when the counts differ
+const ENSURE_REVERSE_DEPENDENCIES_SCRIPT: &str = "
+    if redis.call('SCARD', 'pkgs_indexed:rdeps') == redis.call('SCARD', 'pkgs_set') then
+        return 0
+    end
+    local missing = redis.call('SDIFF', 'pkgs_set', 'pkgs_indexed:rdeps')
+    for _, name in ipairs(missing) do
+        local deps_key = 'pkgs:' .. name .. ':deps'
+        for _, group_key in ipairs(redis.call('SMEMBERS', deps_key)) do
+            local group = string.sub(group_key, #deps_key + 2)
+            for _, dep in ipairs(redis.call('LRANGE', group_key, 0, -1)) do
+                local rdeps_key = 'pkgs:' .. dep .. ':rdeps'
+                redis.call('SADD', rdeps_key .. ':' .. group, name)
+                redis.call('SADD', rdeps_key, rdeps_key .. ':' .. group)
+            end
+        end
+        redis.call('SADD', 'pkgs_indexed:rdeps', name)
+    end
+    for _, name in ipairs(redis.call('SDIFF', 'pkgs_indexed:rdeps', 'pkgs_set')) do
+        redis.call('SREM', 'pkgs_indexed:rdeps', name)
+    end
+    return #missing
+";
+
+// Packages whose search words are read and indexed by one pipeline of `ensure_search_words`
+const WORDS_BACKFILL_BATCH: usize = 500;
+
+// Numeric fields, indexed by `pkgs_by:{field}` sorted sets scored by the field value; `SORT` needs `ALPHA` for the others
+const NUMERIC_FIELDS: [&str; 2] = ["votes", "popularity"];
+// Dates are compared as text like the other backends do, so this field has a lexicographic sorted set instead,
+// with `{last_updated}\0{name}` members of equal score
+const LEX_FIELD: &str = "last_updated";
+
+// redis-rs converts replies while reading them, so only the parsing of package and comment hashes into models is
+// reported as deserialization; everything else is part of the round-trips
 pub struct RedisDb {
-    client: Client
+    // Reconnects on its own and multiplexes commands over one connection, so it never blocks the async runtime
+    connection: ConnectionManager,
+    remove_comments_script: Script,
+    delete_pkg_script: Script,
+    index_words_script: Script,
+    update_script: Script,
+    increment_votes_script: Script,
+    ensure_sort_indexes_script: Script,
+    ensure_reverse_dependencies_script: Script,
+    sum_cardinalities_script: Script,
+    sort_strategy: RedisSortStrategy,
 }
 
 impl RedisDb {
-    pub fn try_new() -> Result<Self> {
-            let redis_host_name = "127.0.0.1";
-            let port = "6379";
-            let redis_password = "redis";
-            let username = "default";
-            let uri_scheme = "redis";
-            let redis_conn_url = format!("{}://{}:{}@{}:{}", uri_scheme, username, redis_password, redis_host_name, port);
-            let client = Client::open(redis_conn_url)?;
-            Ok(Self { client })
+    pub async fn try_new(profile: &ConnectionProfile) -> Result<Self> {
+            let client = Client::open(Self::connection_info(profile)?)?;
+            let connection = ConnectionManager::new(client).await?;
+            let mut db = Self {
+                connection,
+                remove_comments_script: Script::new(REMOVE_COMMENTS_SCRIPT),
+                delete_pkg_script: Script::new(DELETE_PKG_SCRIPT),
+                index_words_script: Script::new(INDEX_WORDS_SCRIPT),
+                update_script: Script::new(UPDATE_SCRIPT),
+                increment_votes_script: Script::new(INCREMENT_VOTES_SCRIPT),
+                ensure_sort_indexes_script: Script::new(ENSURE_SORT_INDEXES_SCRIPT),
+                ensure_reverse_dependencies_script: Script::new(ENSURE_REVERSE_DEPENDENCIES_SCRIPT),
+                sum_cardinalities_script: Script::new(SUM_CARDINALITIES_SCRIPT),
+                sort_strategy: profile.redis.unwrap_or_default().sort_strategy,
+            };
+            db.load_scripts().await?;
+            Ok(db)
+    }
+
+    // Scripts run inside pipelines are sent with EVALSHA, so their text is not part of every call. They are loaded
+    // when connecting and again when the server lost them, e.g. after a restart or a SCRIPT FLUSH
+    async fn load_scripts(&mut self) -> Result<()> {
+        let mut pipe = redis::pipe();
+        for script in PIPELINED_SCRIPTS {
+            pipe.cmd("SCRIPT").arg("LOAD").arg(script).ignore();
+        }
+        pipe.query_async::<_, ()>(&mut self.connection).await?;
+        Ok(())
+    }
+
+    // Runs a pipeline calling scripts, loading them and running it again on NOSCRIPT. Commands of the first attempt
+    // other than the failed EVALSHA may have run, so these pipelines must be safe to repeat
+    async fn query_pipeline<T: FromRedisValue>(&mut self, pipe: &redis::Pipeline, timer: &mut PhaseTimer) -> Result<T> {
+        let result = pipe.query_async(&mut self.connection).await;
+        timer.round_trip();
+        match result {
+            Err(e) if e.kind() == ErrorKind::NoScriptError => {
+                self.load_scripts().await?;
+                let result = pipe.query_async(&mut self.connection).await?;
+                timer.round_trips(2);
+                Ok(result)
+            },
+            result => Ok(result?),
+        }
+    }
+
+    // Credentials are set on the parsed info rather than formatted into the url, where they would need escaping
+    fn connection_info(profile: &ConnectionProfile) -> Result<ConnectionInfo> {
+        let uri_scheme = if profile.tls { "rediss" } else { "redis" };
+        let mut info = format!("{}://{}:{}", uri_scheme, profile.host, profile.port).into_connection_info()?;
+        info.redis.username = profile.username.clone();
+        info.redis.password = profile.password.clone();
+        Ok(info)
+    }
+
+    // Queues the sorted set index entries of the package, only written with the `SortedSet` strategy
+    fn index_sort_fields(&self, pipe: &mut redis::Pipeline, pkg: &BasicPackageData) {
+        if self.sort_strategy != RedisSortStrategy::SortedSet {
+            return;
+        }
+        pipe.zadd("pkgs_by:votes", &pkg.name, pkg.votes).ignore()
+            .zadd("pkgs_by:popularity", &pkg.name, pkg.popularity).ignore()
+            .zadd(format!("pkgs_by:{}", LEX_FIELD), format!("{}\0{}", pkg.last_updated, pkg.name), 0).ignore();
+    }
+
+    // Packages written by other clients, such as the scraper, have no search words. Words are split in Rust, so unlike
+    // the other indexes they are backfilled from here: once `pkgs_indexed:words` no longer counts as many packages as
+    // `pkgs_set`, the missing ones are read and indexed and the words of packages deleted by others are dropped
+    async fn ensure_search_words(&mut self, timer: &mut PhaseTimer) -> Result<()> {
+        let (indexed, count): (usize, usize) = redis::pipe()
+            .scard("pkgs_indexed:words")
+            .scard("pkgs_set")
+            .query_async(&mut self.connection)
+            .await?;
+        timer.round_trip();
+        if indexed == count {
+            return Ok(());
+        }
+        let (missing, stale): (Vec<String>, Vec<String>) = redis::pipe()
+            .sdiff(&["pkgs_set", "pkgs_indexed:words"])
+            .sdiff(&["pkgs_indexed:words", "pkgs_set"])
+            .query_async(&mut self.connection)
+            .await?;
+        timer.round_trip();
+
+        for chunk in missing.chunks(WORDS_BACKFILL_BATCH) {
+            let mut pipe = redis::pipe();
+            for name in chunk {
+                pipe.hget(format!("pkgs:{}", name), &["description", "keywords"]);
+            }
+            timer.processing();
+            let fields: Vec<(Option<String>, Option<String>)> = pipe.query_async(&mut self.connection).await?;
+            timer.round_trip();
+            let mut pipe = redis::pipe();
+            for (name, (description, keywords)) in chunk.iter().zip(fields) {
+                let words = search::pkg_words(name, &description.unwrap_or_default(), keywords.as_deref());
+                eval_sha(&mut pipe, &self.index_words_script).arg(1).arg(format!("pkgs:{}", name)).arg(name).arg(words).ignore();
+            }
+            timer.processing();
+            self.query_pipeline::<()>(&pipe, timer).await?;
+        }
+
+        if !stale.is_empty() {
+            let mut pipe = redis::pipe();
+            for name in &stale {
+                eval_sha(&mut pipe, &self.index_words_script).arg(1).arg(format!("pkgs:{}", name)).arg(name).ignore();
+            }
+            pipe.srem("pkgs_indexed:words", &stale).ignore();
+            timer.processing();
+            self.query_pipeline::<()>(&pipe, timer).await?;
+        }
+        Ok(())
+    }
+}
+
+// Scripts called with `eval_sha`, which `RedisDb::load_scripts` loads
+const PIPELINED_SCRIPTS: [&str; 3] = [DELETE_PKG_SCRIPT, INDEX_WORDS_SCRIPT, SUM_CARDINALITIES_SCRIPT];
+
+fn eval_sha<'a>(pipe: &'a mut redis::Pipeline, script: &Script) -> &'a mut redis::Pipeline {
+    pipe.cmd("EVALSHA").arg(script.get_hash())
+}
+
+// Fields of the `pkgs:{name}` hash, names match the ones read by `PackageData::try_from`
+fn hash_fields(pkg: &PackageData) -> Vec<(&'static str, String)> {
+    let mut fields = vec![
+        ("popularity", pkg.basic.popularity.to_string()),
+        ("last_updated", pkg.basic.last_updated.clone()),
+        ("description", pkg.basic.description.clone()),
+        ("maintainer", pkg.basic.maintainer.clone()),
+        ("version", pkg.basic.version.clone()),
+        ("votes", pkg.basic.votes.to_string()),
+        ("path_to_additional_data", pkg.basic.path_to_additional_data.clone()),
+        ("firstsubmitted", pkg.additional.first_submitted.clone()),
+        ("gitcloneurl", pkg.additional.git_clone_url.clone()),
+        ("submitter", pkg.additional.submitter.clone()),
+    ];
+    let optional_fields = [
+        ("conflicts", &pkg.additional.confilcts),
+        ("provides", &pkg.additional.provides),
+        ("keywords", &pkg.additional.keywords),
+        ("licenses", &pkg.additional.license),
+    ];
+    fields.extend(optional_fields.into_iter().filter_map(|(key, value)| value.clone().map(|v| (key, v))));
+    fields
+}
+
+fn patch_fields(patch: &BasicPackagePatch) -> Vec<(&'static str, String)> {
+    let fields = [
+        ("version", patch.version.clone()),
+        ("path_to_additional_data", patch.path_to_additional_data.clone()),
+        ("votes", patch.votes.map(|votes| votes.to_string())),
+        ("popularity", patch.popularity.map(|popularity| popularity.to_string())),
+        ("description", patch.description.clone()),
+        ("maintainer", patch.maintainer.clone()),
+        ("last_updated", patch.last_updated.clone()),
+    ];
+    fields.into_iter().filter_map(|(key, value)| value.map(|v| (key, v))).collect()
+}
+
+#[async_trait]
+impl Connect for RedisDb {
+    async fn connect(profile: &ConnectionProfile) -> Result<Self> {
+        Self::try_new(profile).await
     }
 }
 
@@ -33,10 +378,8 @@ impl DbActions for RedisDb {
         parts.iter()
             .skip(1)
             .for_each(|arg| _ = cmd.arg(arg));
-
-        let mut connection = self.client.get_connection()?;
         let start = Instant::now();
-        cmd.query(&mut connection)?;
+        cmd.query_async::<_, ()>(&mut self.connection).await?;
         let duration = start.elapsed();
         
         Ok(duration)
@@ -48,178 +391,493 @@ impl DbActions for RedisDb {
         parts.iter()
             .skip(1)
             .for_each(|arg| _ = cmd.arg(arg));
+        let mut timer = PhaseTimer::start();
+        let result: String = cmd.query_async(&mut self.connection).await?;
+        timer.round_trip();
 
-        let mut connection = self.client.get_connection()?;
-        let start = Instant::now();
-        let result: String = cmd.query(&mut connection)?;
-        let duration = start.elapsed();
-
-        Ok(DbResponse { result, duration })
+        Ok(timer.respond(result))
     }
 
     async fn sort_pkgs_by_field_with_limit(&mut self, field: &str, limit_start: u32, limit_end: u32) -> Result<DbResponse<Vec<String>>> {
-        let mut connection = self.client.get_connection()?;
-        let mut cmd = redis::cmd("SORT");
-        cmd.arg(&["pkgs_set", "by", &format!("pkgs:*->{}", field), "limit", &limit_start.to_string(), &limit_end.to_string(), "DESC"]);
-
-        let start = Instant::now();
-        let result: Vec<String> = cmd.query(&mut connection)?;
-        let duration = start.elapsed();
+        if limit_end <= limit_start {
+            return Ok(DbResponse::untimed(vec![]));
+        }
+        let count = (limit_end - limit_start) as isize;
+        let offset = limit_start as isize;
+        let key = format!("pkgs_by:{}", field);
+
+        // Both strategies break ties by name in descending order, sorted sets and SORT alike compare equal scores by member
+        let mut timer = PhaseTimer::start();
+        if self.sort_strategy == RedisSortStrategy::SortedSet {
+            self.ensure_sort_indexes_script.invoke_async::<_, ()>(&mut self.connection).await?;
+            timer.round_trip();
+        }
+        let result: Vec<String> = match self.sort_strategy {
+            RedisSortStrategy::SortedSet if NUMERIC_FIELDS.contains(&field) => {
+                self.connection.zrevrange(&key, offset, offset + count - 1).await?
+            },
+            RedisSortStrategy::SortedSet if field == LEX_FIELD => {
+                let members: Vec<String> = self.connection.zrevrangebylex_limit(&key, "+", "-", offset, count).await?;
+                members.into_iter()
+                    .map(|member| member.split_once('\0').map(|(_, name)| name.to_owned()).unwrap_or(member))
+                    .collect()
+            },
+            _ => {
+                let mut cmd = redis::cmd("SORT");
+                cmd.arg(&["pkgs_set", "BY", &format!("pkgs:*->{}", field), "LIMIT", &offset.to_string(), &count.to_string(), "DESC"]);
+                if !NUMERIC_FIELDS.contains(&field) {
+                    cmd.arg("ALPHA");
+                }
+                cmd.query_async(&mut self.connection).await?
+            },
+        };
+        timer.round_trip();
 
-        Ok(DbResponse { result, duration })
+        Ok(timer.respond(result))
     }
 
     async fn get_most_voted_pkgs(&mut self, number: u32) -> Result<DbResponse<Vec<BasicPackageData>>> {
+        let mut timer = PhaseTimer::start();
         let pkgs_name_response = self.sort_pkgs_by_field_with_limit("votes", 0, number).await?;
-        
-        let mut connection = self.client.get_connection()?;
-
-        let start = Instant::now();
+        timer.nested(&pkgs_name_response.timings);
 
         let mut result = Vec::new();
         for name in &pkgs_name_response.result {
-            let mut pkg_dict: HashMap<String, String> = connection.hgetall(format!("pkgs:{}", name))?;
+            let mut pkg_dict: HashMap<String, String> = self.connection.hgetall(format!("pkgs:{}", name)).await?;
+            timer.round_trip();
             pkg_dict.insert("name".into(), name.into());
     
             let pkg = PackageData::try_from(pkg_dict)?;
             result.push(pkg.basic);
+            timer.deserialization();
         }
 
-        let duration = start.elapsed();
-        Ok(DbResponse { result, duration: duration + pkgs_name_response.duration })
+        Ok(timer.respond(result))
     }
 
     async fn insert_pkg(&mut self, pkg: &PackageData) -> Result<DbResponse<()>> {
-        let mut connection = self.client.get_connection()?;
-        let start = Instant::now();
-        connection.hset_multiple(
-            format!("pkgs:{}", pkg.basic.name),
-            &[
-                ("popularity", pkg.basic.popularity.to_string().as_str()),
-                ("last_updated", pkg.basic.last_updated.as_str()),
-                ("description", pkg.basic.description.as_str()),
-                ("maintainer", pkg.basic.maintainer.as_str()),
-                ("version", pkg.basic.version.as_str()),
-                ("votes", pkg.basic.votes.to_string().as_str()),
-                ("path_to_additional_data", pkg.basic.path_to_additional_data.as_str()),
-                ("firstsubmitted", pkg.additional.first_submitted.as_str()),
-                ("gitcloneurl", pkg.additional.git_clone_url.as_str()),
-                ("submitter", pkg.additional.submitter.as_str()),
-                (
-                    "confilcts",
-                    pkg.additional.confilcts.as_ref().map(|s| s.as_str()).unwrap_or(""),
-                ),
-                (
-                    "provides",
-                    pkg.additional.provides.as_ref().map(|s| s.as_str()).unwrap_or(""),
-
-                ),
-                (
-                    "keywords",
-                    pkg.additional.keywords.as_ref().map(|s| s.as_str()).unwrap_or(""),
-                ),
-                (
-                    "license",
-                    pkg.additional.license.as_ref().map(|s| s.as_str()).unwrap_or(""),
-                ),
-            ],
-        )?;
-
-        connection.sadd("pkgs_set", &pkg.basic.name)?;
+        let mut timer = PhaseTimer::start();
+        // Re-inserting replaces the package, so stale comments and index entries do not survive
+        self.delete_pkg_script
+            .key(format!("pkgs:{}", pkg.basic.name))
+            .arg(&pkg.basic.name)
+            .invoke_async::<_, ()>(&mut self.connection)
+            .await?;
+        self.connection.hset_multiple::<_, _, _, ()>(format!("pkgs:{}", pkg.basic.name), &hash_fields(pkg)).await?;
+
+        self.connection.sadd::<_, _, ()>("pkgs_set", &pkg.basic.name).await?;
+        self.connection.sadd::<_, _, ()>(format!("maintainers:{}", pkg.basic.maintainer), &pkg.basic.name).await?;
+        self.connection.sadd::<_, _, ()>(format!("submitters:{}", pkg.additional.submitter), &pkg.basic.name).await?;
+        timer.round_trips(5);
+        if self.sort_strategy == RedisSortStrategy::SortedSet {
+            let mut index_pipe = redis::pipe();
+            self.index_sort_fields(&mut index_pipe, &pkg.basic);
+            index_pipe.query_async::<_, ()>(&mut self.connection).await?;
+            timer.round_trip();
+        }
+        let words = search::pkg_words(&pkg.basic.name, &pkg.basic.description, pkg.additional.keywords.as_deref());
+        timer.processing();
+        self.index_words_script
+            .key(format!("pkgs:{}", pkg.basic.name))
+            .arg(&pkg.basic.name)
+            .arg(words)
+            .invoke_async::<_, ()>(&mut self.connection)
+            .await?;
+        timer.round_trip();
 
         for (idx, comment) in pkg.comments.iter().enumerate() {
-            connection.hset_multiple(
+            self.connection.hset_multiple::<_, _, _, ()>(
                 format!("pkgs:{}:cmnts:{}", pkg.basic.name, idx + 1),
                 &[("header", &comment.header), ("content", &comment.content)],
-            )?;
+            ).await?;
 
-            connection.sadd(
+            self.connection.sadd::<_, _, ()>(
                 format!("pkgs:{}:cmnts", pkg.basic.name),
                 format!("pkgs:{}:cmnts:{}", pkg.basic.name, idx + 1),
-            )?;
+            ).await?;
+            timer.round_trips(2);
         }
 
         for dependency in &pkg.dependencies {
             for dep in &dependency.packages {
-                connection.rpush(
+                self.connection.rpush::<_, _, ()>(
                     format!("pkgs:{}:deps:{}", pkg.basic.name, dependency.group),
                     dep,
-                )?;
+                ).await?;
+
+                let rdeps_key = format!("pkgs:{}:rdeps", dep);
+                let rdeps_group_key = format!("{}:{}", rdeps_key, dependency.group);
+                self.connection.sadd::<_, _, ()>(&rdeps_group_key, &pkg.basic.name).await?;
+                self.connection.sadd::<_, _, ()>(&rdeps_key, &rdeps_group_key).await?;
+                timer.round_trips(3);
             }
 
-            connection.sadd(
+            self.connection.sadd::<_, _, ()>(
                 format!("pkgs:{}:deps", pkg.basic.name),
                 format!("pkgs:{}:deps:{}", pkg.basic.name, dependency.group),
-            )?;
+            ).await?;
+            timer.round_trip();
         }
-        let duration = start.elapsed();
-        Ok(DbResponse { result: (), duration })
+        self.connection.sadd::<_, _, ()>("pkgs_indexed:rdeps", &pkg.basic.name).await?;
+        timer.round_trip();
+        Ok(timer.respond(()))
+    }
+
+    async fn insert_pkgs(&mut self, pkgs: &[PackageData]) -> Result<DbResponse<()>> {
+        let mut timer = PhaseTimer::start();
+        // Same commands as `insert_pkg`, sent in a single MULTI/EXEC round-trip
+        let mut pipe = redis::pipe();
+        pipe.atomic();
+        for pkg in pkgs {
+            let name = &pkg.basic.name;
+            eval_sha(&mut pipe, &self.delete_pkg_script).arg(1).arg(format!("pkgs:{}", name)).arg(name).ignore();
+            pipe.hset_multiple(format!("pkgs:{}", name), &hash_fields(pkg)).ignore()
+                .sadd("pkgs_set", name).ignore()
+                .sadd(format!("maintainers:{}", pkg.basic.maintainer), name).ignore()
+                .sadd(format!("submitters:{}", pkg.additional.submitter), name).ignore();
+            self.index_sort_fields(&mut pipe, &pkg.basic);
+            eval_sha(&mut pipe, &self.index_words_script).arg(1).arg(format!("pkgs:{}", name)).arg(name)
+                .arg(search::pkg_words(&pkg.basic.name, &pkg.basic.description, pkg.additional.keywords.as_deref())).ignore();
+
+            for (idx, comment) in pkg.comments.iter().enumerate() {
+                let comment_key = format!("pkgs:{}:cmnts:{}", name, idx + 1);
+                pipe.hset_multiple(&comment_key, &[("header", &comment.header), ("content", &comment.content)]).ignore()
+                    .sadd(format!("pkgs:{}:cmnts", name), &comment_key).ignore();
+            }
+
+            for dependency in &pkg.dependencies {
+                let group_key = format!("pkgs:{}:deps:{}", name, dependency.group);
+                // RPUSH without values is a syntax error and would abort the whole transaction
+                if !dependency.packages.is_empty() {
+                    pipe.rpush(&group_key, &dependency.packages).ignore();
+                }
+                pipe.sadd(format!("pkgs:{}:deps", name), &group_key).ignore();
+
+                for dep in &dependency.packages {
+                    let rdeps_key = format!("pkgs:{}:rdeps", dep);
+                    let rdeps_group_key = format!("{}:{}", rdeps_key, dependency.group);
+                    pipe.sadd(&rdeps_group_key, name).ignore()
+                        .sadd(&rdeps_key, &rdeps_group_key).ignore();
+                }
+            }
+            pipe.sadd("pkgs_indexed:rdeps", name).ignore();
+        }
+        timer.processing();
+        self.query_pipeline::<()>(&pipe, &mut timer).await?;
+        Ok(timer.respond(()))
     }
 
     async fn get_pkg(&mut self, name: &str) -> Result<DbResponse<PackageData>> {
-        let mut conn = self.client.get_connection()?;
-        let start = Instant::now();
-        let mut pkg_dict: HashMap<String, String> = conn.hgetall(format!("pkgs:{}", name))?;
+        let mut timer = PhaseTimer::start();
+        let mut pkg_dict: HashMap<String, String> = self.connection.hgetall(format!("pkgs:{}", name)).await?;
+        timer.round_trip();
         pkg_dict.insert("name".into(), name.into());
 
         let mut pkg = PackageData::try_from(pkg_dict).map_err(|e| anyhow!(e))?;
+        timer.deserialization();
 
-        let cmnts_list: Vec<String> = conn.smembers(format!("pkgs:{}:cmnts", pkg.basic.name))?;
+        let cmnts_list: Vec<String> = self.connection.smembers(format!("pkgs:{}:cmnts", pkg.basic.name)).await?;
+        timer.round_trip();
 
         let mut comments = vec![];
 
         for cmnt in cmnts_list {
-            let cmnt_dict: HashMap<String, String> = conn.hgetall(cmnt)?;
+            let cmnt_dict: HashMap<String, String> = self.connection.hgetall(cmnt).await?;
+            timer.round_trip();
             comments.push(Comment::try_from(cmnt_dict)?);
+            timer.deserialization();
         }
 
         pkg.comments = comments;
 
-        let group_list: Vec<String> = conn.smembers(format!("pkgs:{}:deps", pkg.basic.name))?;
+        let group_list: Vec<String> = self.connection.smembers(format!("pkgs:{}:deps", pkg.basic.name)).await?;
+        timer.round_trip();
+        let group_prefix = format!("pkgs:{}:deps:", pkg.basic.name);
 
         let mut dependencies = vec![];
 
-        for group in group_list {
-            let packages: Vec<String> = conn.lrange(&group, 0, -1)?;
+        for group_key in group_list {
+            let packages: Vec<String> = self.connection.lrange(&group_key, 0, -1).await?;
+            timer.round_trip();
+            let group = group_key.strip_prefix(&group_prefix).unwrap_or(&group_key).to_owned();
 
             dependencies.push(PackageDependency { group, packages });
         }
-        let duration = start.elapsed();
         pkg.dependencies = dependencies;
 
-        Ok(DbResponse { result: pkg, duration })
+        Ok(timer.respond(pkg))
+    }
+
+    async fn contains_pkg(&mut self, pkg_name: &str) -> Result<DbResponse<bool>> {
+        let mut timer = PhaseTimer::start();
+        let result: bool = self.connection.sismember("pkgs_set", pkg_name).await?;
+        timer.round_trip();
+        Ok(timer.respond(result))
     }
 
     async fn remove_comments(&mut self, pkg_name: &str) -> Result<DbResponse<()>> {
-        let mut connection = self.client.get_connection()?;
-        let start = Instant::now();
-        connection.del(format!("pkgs:{}:cmnts", pkg_name))?;
-        let duration = start.elapsed();
-        Ok(DbResponse { result: (), duration })
+        let mut timer = PhaseTimer::start();
+        self.remove_comments_script
+            .key(format!("pkgs:{}:cmnts", pkg_name))
+            .invoke_async::<_, ()>(&mut self.connection)
+            .await?;
+        timer.round_trip();
+        Ok(timer.respond(()))
     }
 
-    async fn get_packages_occurences_in_deps(&mut self, pkg_deps_names: &Vec<String>) -> Result<DbResponse<HashMap<String, u32>>> {
-        let mut connection = self.client.get_connection()?;
-        let mut data: HashMap<String, u32> = HashMap::new();
-        pkg_deps_names.iter().for_each(|name| _ = data.insert(name.to_owned(), 0));
+    async fn delete_pkg(&mut self, pkg_name: &str) -> Result<DbResponse<()>> {
+        let mut timer = PhaseTimer::start();
+        self.delete_pkg_script
+            .key(format!("pkgs:{}", pkg_name))
+            .arg(pkg_name)
+            .invoke_async::<_, ()>(&mut self.connection)
+            .await?;
+        timer.round_trip();
+        Ok(timer.respond(()))
+    }
 
-        let start = Instant::now();
-        let all_pkg_names: Vec<String> = connection.smembers("pkgs_set")?;
-        for pkg_name in &all_pkg_names {
-            let group_list: Vec<String> = connection.smembers(format!("pkgs:{}:deps", pkg_name))?;
-            for pkg_dep_name in pkg_deps_names {
-                if group_list.contains(&format!("pkgs:{}:deps:{}", pkg_name, pkg_dep_name)) {
-                    if data.contains_key(pkg_dep_name) {
-                        let count = data.get(pkg_dep_name).unwrap() + 1;
-                        data.insert(pkg_dep_name.to_owned(), count);
+    async fn get_reverse_dependencies(&mut self, pkg_name: &str, groups: &[String]) -> Result<DbResponse<Vec<String>>> {
+        let mut timer = PhaseTimer::start();
+        self.ensure_reverse_dependencies_script.invoke_async::<_, ()>(&mut self.connection).await?;
+        timer.round_trip();
+        let rdeps_key = format!("pkgs:{}:rdeps", pkg_name);
+        let keys: Vec<String> = match groups {
+            [] => {
+                let keys = self.connection.smembers(&rdeps_key).await?;
+                timer.round_trip();
+                keys
+            },
+            _ => groups.iter().map(|group| format!("{}:{}", rdeps_key, group)).collect(),
+        };
+        let mut result: Vec<String> = if keys.is_empty() {
+            vec![]
+        } else {
+            timer.processing();
+            let result = self.connection.sunion(&keys).await?;
+            timer.round_trip();
+            result
+        };
+        result.sort();
+        Ok(timer.respond(result))
+    }
+
+    async fn find_orphaned_keys(&mut self, remove: bool) -> Result<DbResponse<Vec<String>>> {
+        let mut timer = PhaseTimer::start();
+        let pkg_names: HashSet<String> = self.connection.smembers("pkgs_set").await?;
+        timer.round_trip();
+
+        let mut keys: Vec<String> = vec![];
+        for pattern in ["pkgs:*:cmnts*", "pkgs:*:deps*"] {
+            let mut iter = self.connection.scan_match::<_, String>(pattern).await?;
+            while let Some(key) = iter.next_item().await {
+                keys.push(key);
+            }
+            // The cursor fetches batches on its own, so a whole scan is counted as one round-trip
+            timer.round_trip();
+        }
+
+        // Comment hashes and dependency lists are reachable only through the set of their package
+        let mut owner_sets: HashMap<String, HashSet<String>> = HashMap::new();
+        let mut result = vec![];
+        for key in keys {
+            let parts: Vec<&str> = key.splitn(4, ':').collect();
+            let orphaned = match parts.as_slice() {
+                ["pkgs", name, "cmnts" | "deps"] => !pkg_names.contains(*name),
+                ["pkgs", name, kind @ ("cmnts" | "deps"), _] => {
+                    let owner_key = format!("pkgs:{}:{}", name, kind);
+                    if !owner_sets.contains_key(&owner_key) {
+                        timer.processing();
+                        let members: HashSet<String> = self.connection.smembers(&owner_key).await?;
+                        timer.round_trip();
+                        owner_sets.insert(owner_key.clone(), members);
                     }
-                }
+                    !pkg_names.contains(*name) || !owner_sets[&owner_key].contains(&key)
+                },
+                _ => false,
+            };
+            if orphaned {
+                result.push(key);
+            }
+        }
+
+        if remove && !result.is_empty() {
+            timer.processing();
+            self.connection.del::<_, ()>(&result).await?;
+            timer.round_trip();
+        }
+        Ok(timer.respond(result))
+    }
+
+    async fn search_pkgs(&mut self, query: &str, limit: u32) -> Result<DbResponse<Vec<String>>> {
+        let words = search::query_words(query)?;
+        let mut timer = PhaseTimer::start();
+        self.ensure_search_words(&mut timer).await?;
+        let mut matched: Option<HashSet<String>> = None;
+        for word in &words {
+            // Lexicographic range of the words starting with the query word, 0xFF sorts after any UTF-8 byte
+            let min = [b"[".as_slice(), word.as_bytes()].concat();
+            let max = [b"[".as_slice(), word.as_bytes(), b"\xff".as_slice()].concat();
+            timer.processing();
+            let extended: Vec<String> = self.connection.zrangebylex("search:words", min, max).await?;
+            timer.round_trip();
+            let keys: Vec<String> = extended.iter().map(|word| format!("search:word:{}", word)).collect();
+            let pkgs: HashSet<String> = match keys.is_empty() {
+                true => HashSet::new(),
+                false => {
+                    timer.processing();
+                    let pkgs = self.connection.sunion(keys).await?;
+                    timer.round_trip();
+                    pkgs
+                },
+            };
+            matched = Some(match matched {
+                Some(matched) => matched.intersection(&pkgs).cloned().collect(),
+                None => pkgs,
+            });
+            if matched.as_ref().is_some_and(HashSet::is_empty) {
+                break;
             }
         }
-        let duration = start.elapsed();        
-        Ok(DbResponse { result: data, duration })
+
+        let names: Vec<String> = matched.unwrap_or_default().into_iter().collect();
+        let mut pipe = redis::pipe();
+        for name in &names {
+            pipe.hget(format!("pkgs:{}", name), "popularity");
+        }
+        let popularity: Vec<f32> = match names.is_empty() {
+            true => vec![],
+            false => {
+                timer.processing();
+                let popularity = pipe.query_async(&mut self.connection).await?;
+                timer.round_trip();
+                popularity
+            },
+        };
+        let result = search::rank(names.into_iter().zip(popularity).collect(), limit);
+        Ok(timer.respond(result))
+    }
+
+    async fn get_pkgs_by_maintainer(&mut self, maintainer: &str) -> Result<DbResponse<Vec<String>>> {
+        let mut timer = PhaseTimer::start();
+        let mut result: Vec<String> = self.connection.smembers(format!("maintainers:{}", maintainer)).await?;
+        timer.round_trip();
+        result.sort();
+        Ok(timer.respond(result))
+    }
+
+    async fn get_pkgs_by_submitter(&mut self, submitter: &str) -> Result<DbResponse<Vec<String>>> {
+        let mut timer = PhaseTimer::start();
+        let mut result: Vec<String> = self.connection.smembers(format!("submitters:{}", submitter)).await?;
+        timer.round_trip();
+        result.sort();
+        Ok(timer.respond(result))
+    }
+
+    async fn get_catalog_stats(&mut self) -> Result<DbResponse<CatalogStats>> {
+        let mut timer = PhaseTimer::start();
+        let names: Vec<String> = self.connection.smembers("pkgs_set").await?;
+        timer.round_trip();
+        let mut fields_pipe = redis::pipe();
+        let mut comments_pipe = redis::pipe();
+        for name in &names {
+            fields_pipe.hget(format!("pkgs:{}", name), &["votes", "popularity", "licenses", "maintainer"]);
+            comments_pipe.scard(format!("pkgs:{}:cmnts", name));
+        }
+
+        let mut aggregates = Aggregates::default();
+        if !names.is_empty() {
+            timer.processing();
+            let fields: Vec<(i32, f32, Option<String>, String)> = fields_pipe.query_async(&mut self.connection).await?;
+            let comments: Vec<u64> = comments_pipe.query_async(&mut self.connection).await?;
+            timer.round_trips(2);
+            for ((votes, popularity, license, maintainer), comments) in fields.into_iter().zip(comments) {
+                aggregates.add_pkg(votes, popularity, license.as_deref(), &maintainer, comments);
+            }
+        }
+        let result = aggregates.into_stats();
+        Ok(timer.respond(result))
     }
 
+    async fn update_pkg_fields(&mut self, pkg_name: &str, patch: &BasicPackagePatch) -> Result<DbResponse<()>> {
+        patch.ensure_not_empty()?;
+        let mut timer = PhaseTimer::start();
+        let mut invocation = self.update_script.key(format!("pkgs:{}", pkg_name));
+        for (field, value) in patch_fields(patch) {
+            invocation.arg(field).arg(value);
+        }
+        timer.processing();
+        invocation.invoke_async::<_, ()>(&mut self.connection).await?;
+        timer.round_trip();
+
+        // The description is one of the sources of search words
+        if let Some(description) = &patch.description {
+            let keywords: Option<String> = self.connection.hget(format!("pkgs:{}", pkg_name), "keywords").await?;
+            timer.round_trip();
+            let words = search::pkg_words(pkg_name, description, keywords.as_deref());
+            timer.processing();
+            self.index_words_script
+                .key(format!("pkgs:{}", pkg_name))
+                .arg(pkg_name)
+                .arg(words)
+                .invoke_async::<_, ()>(&mut self.connection)
+                .await?;
+            timer.round_trip();
+        }
+        Ok(timer.respond(()))
+    }
+
+    async fn increment_votes(&mut self, pkg_name: &str, delta: i32) -> Result<DbResponse<i32>> {
+        let mut timer = PhaseTimer::start();
+        let result: i32 = self.increment_votes_script
+            .key(format!("pkgs:{}", pkg_name))
+            .arg(delta)
+            .invoke_async(&mut self.connection)
+            .await?;
+        timer.round_trip();
+        Ok(timer.respond(result))
+    }
+
+    async fn get_packages_occurences_in_deps(&mut self, pkg_names: &[String], groups: &[String]) -> Result<DbResponse<HashMap<String, u32>>> {
+        if pkg_names.is_empty() {
+            return Ok(DbResponse::untimed(HashMap::new()));
+        }
+
+        let mut timer = PhaseTimer::start();
+        self.ensure_reverse_dependencies_script.invoke_async::<_, ()>(&mut self.connection).await?;
+        timer.round_trip();
+        // Served by the reverse dependency sets, each one holds the packages listing the name in a single group
+        let group_keys: Vec<Vec<String>> = match groups {
+            [] => {
+                let mut pipe = redis::pipe();
+                for pkg_name in pkg_names {
+                    pipe.smembers(format!("pkgs:{}:rdeps", pkg_name));
+                }
+                timer.processing();
+                let group_keys = pipe.query_async(&mut self.connection).await?;
+                timer.round_trip();
+                group_keys
+            },
+            _ => pkg_names.iter()
+                .map(|pkg_name| groups.iter().map(|group| format!("pkgs:{}:rdeps:{}", pkg_name, group)).collect())
+                .collect(),
+        };
+
+        let mut pipe = redis::pipe();
+        for keys in &group_keys {
+            eval_sha(&mut pipe, &self.sum_cardinalities_script).arg(keys.len()).arg(keys);
+        }
+        timer.processing();
+        let counts: Vec<u32> = self.query_pipeline(&pipe, &mut timer).await?;
+        let result = pkg_names.iter().cloned().zip(counts).collect();
+        Ok(timer.respond(result))
+    }
+
+    fn is_connection_error(&self, error: &anyhow::Error) -> bool {
+        error.chain().any(|cause| match cause.downcast_ref::<redis::RedisError>() {
+            Some(e) => e.is_io_error() || e.is_connection_dropped() || e.is_connection_refusal() || e.is_timeout(),
+            None => cause.is::<std::io::Error>(),
+        })
+    }
 }
 
 #[cfg(test)]
@@ -227,11 +885,25 @@ mod test {
     use super::RedisDb;
     use anyhow::{Result, Ok};
     use super::DbActions;
+    use crate::{config::ConnectionConfig, commands::Db};
+
+    #[test]
+    fn test_connection_info() -> Result<()> {
+        let mut profile = ConnectionConfig::default().profile(&Db::Redis, None)?;
+        profile.username = Some("user@host".to_string());
+        profile.password = Some("p@ss:w/rd#1".to_string());
+        let info = RedisDb::connection_info(&profile)?;
+        assert_eq!(info.addr.to_string(), format!("{}:{}", profile.host, profile.port));
+        assert_eq!(info.redis.username, profile.username);
+        assert_eq!(info.redis.password, profile.password);
+        Ok(())
+    }
 
     #[tokio::test]
     async fn ss() -> Result<()> {
-        let mut db = RedisDb::try_new()?;
-        let result = db.get_packages_occurences_in_deps(&vec!["rust".to_string(), "go".to_string(), "sudo".to_string()]).await?;
+        let profile = ConnectionConfig::default().profile(&Db::Redis, None)?;
+        let mut db = RedisDb::try_new(&profile).await?;
+        let result = db.get_packages_occurences_in_deps(&["rust".to_string(), "go".to_string(), "sudo".to_string()], &[]).await?;
         print!("{:?}", result.result);
         Ok(())
     }
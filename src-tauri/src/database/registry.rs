@@ -0,0 +1,189 @@
+use std::{collections::HashMap, future::Future, ops::{Deref, DerefMut}, pin::Pin, sync::Arc, time::{Duration, Instant}};
+
+use anyhow::{Result, anyhow};
+use async_trait::async_trait;
+use serde::Serialize;
+use tokio::sync::{Mutex, OwnedMutexGuard};
+
+use crate::{commands::Db, config::{ConnectionConfig, ConnectionProfile, DEFAULT_PROFILE}};
+
+use super::{DbActions, DbResponse};
+
+pub type Backend = Box<dyn DbActions + Send>;
+
+type BoxFuture<'a, T> = Pin<Box<dyn Future<Output = T> + Send + 'a>>;
+type Connector = for<'a> fn(&'a ConnectionProfile) -> BoxFuture<'a, Result<Backend>>;
+// Empty until the profile is first used, and again after its backend was dropped
+type Slot = Arc<Mutex<Option<Backend>>>;
+
+/// Creates a backend from a connection profile, required to register it in the `BackendRegistry`.
+#[async_trait]
+pub trait Connect: DbActions + Send + Sized + 'static {
+    async fn connect(profile: &ConnectionProfile) -> Result<Self>;
+}
+
+fn connect_boxed<T: Connect>(profile: &ConnectionProfile) -> BoxFuture<'_, Result<Backend>> {
+    Box::pin(async move {
+        let backend: Backend = Box::new(T::connect(profile).await?);
+        Ok(backend)
+    })
+}
+
+struct Registration {
+    connector: Connector,
+    // Long-lived backends per connection profile. The map is only locked to find the slot of a profile, so commands
+    // on different profiles run concurrently and only wait for the ones using the same backend
+    backends: Mutex<HashMap<String, Slot>>,
+}
+
+impl Registration {
+    async fn slot(&self, profile_name: &str) -> Slot {
+        let mut backends = self.backends.lock().await;
+        backends.entry(profile_name.to_owned()).or_default().clone()
+    }
+}
+
+/// Maps every `Db` to its `DbActions` implementation and keeps the connected backends shared by all commands.
+/// A backend is dropped after an operation failed because of its connection, so the next call reconnects instead of
+/// reusing a broken connection.
+pub struct BackendRegistry {
+    config: ConnectionConfig,
+    registrations: HashMap<Db, Registration>,
+}
+
+impl BackendRegistry {
+    pub fn new(config: ConnectionConfig) -> Self {
+        Self { config, registrations: HashMap::new() }
+    }
+
+    pub fn register<T: Connect>(mut self, db: Db) -> Self {
+        let registration = Registration {
+            connector: connect_boxed::<T>,
+            backends: Mutex::new(HashMap::new()),
+        };
+        self.registrations.insert(db, registration);
+        self
+    }
+
+    pub fn config(&self) -> &ConnectionConfig {
+        &self.config
+    }
+
+    pub fn registered(&self) -> Vec<Db> {
+        let mut registered: Vec<Db> = self.registrations.keys().copied().collect();
+        registered.sort();
+        registered
+    }
+
+    pub async fn acquire(&self, db: Db, profile: Option<&str>) -> Result<BackendGuard> {
+        let registration = self.registration(&db)?;
+        let profile_name = profile.unwrap_or(DEFAULT_PROFILE);
+
+        let mut backend = registration.slot(profile_name).await.lock_owned().await;
+        let mut connect = Duration::ZERO;
+        if backend.is_none() {
+            let profile = self.config.profile(&db, profile)?;
+            let start = Instant::now();
+            *backend = Some((registration.connector)(&profile).await?);
+            connect = start.elapsed();
+        }
+        Ok(BackendGuard { backend, connect })
+    }
+
+    /// Drops the current backend of the given profile and connects again, returning the connection setup time.
+    pub async fn reconnect(&self, db: Db, profile: Option<&str>) -> Result<Duration> {
+        let registration = self.registration(&db)?;
+        let profile_name = profile.unwrap_or(DEFAULT_PROFILE);
+        let profile = self.config.profile(&db, profile)?;
+
+        let mut backend = registration.slot(profile_name).await.lock_owned().await;
+        *backend = None;
+
+        let start = Instant::now();
+        *backend = Some((registration.connector)(&profile).await?);
+        Ok(start.elapsed())
+    }
+
+    fn registration(&self, db: &Db) -> Result<&Registration> {
+        self.registrations
+            .get(db)
+            .ok_or(anyhow!("No backend registered for {:?}", db))
+    }
+}
+
+/// Exclusive access to a connected backend for the duration of a single command.
+pub struct BackendGuard {
+    backend: OwnedMutexGuard<Option<Backend>>,
+    // Time spent connecting when acquiring, zero if the backend was already connected
+    connect: Duration,
+}
+
+impl BackendGuard {
+    /// Passes the result through, dropping the backend if the operation failed because of the connection.
+    pub fn check<R>(mut self, result: Result<R>) -> Result<R> {
+        if let Err(e) = &result {
+            if self.is_connection_error(e) {
+                *self.backend = None;
+            }
+        }
+        result
+    }
+
+    /// Like `check`, additionally reporting the connection setup this call waited for in the response timings.
+    pub fn check_response<T: Serialize>(self, result: Result<DbResponse<T>>) -> Result<DbResponse<T>> {
+        let connect = self.connect;
+        self.check(result).map(|mut response| {
+            response.timings.connect = connect;
+            response
+        })
+    }
+}
+
+impl Deref for BackendGuard {
+    type Target = Backend;
+
+    fn deref(&self) -> &Self::Target {
+        self.backend.as_ref().expect("Backend is present while guard is held")
+    }
+}
+
+impl DerefMut for BackendGuard {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        self.backend.as_mut().expect("Backend is present while guard is held")
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{io, time::Duration};
+
+    use anyhow::{Result, Ok};
+    use serde_json::json;
+
+    use super::BackendRegistry;
+    use crate::{commands::Db, database::{DbActions, InMemoryDb}};
+
+    #[tokio::test]
+    async fn test_acquire() -> Result<()> {
+        let config = serde_json::from_value(json!({ "InMemory": { "other": { "database": "other" } } }))?;
+        let registry = BackendRegistry::new(config).register::<InMemoryDb>(Db::InMemory);
+
+        // A command holding the backend of one profile does not keep the other profiles waiting
+        let default = registry.acquire(Db::InMemory, None).await?;
+        let other = tokio::time::timeout(Duration::from_secs(1), registry.acquire(Db::InMemory, Some("other"))).await??;
+        drop(other);
+        assert!(tokio::time::timeout(Duration::from_millis(50), registry.acquire(Db::InMemory, None)).await.is_err());
+        drop(default);
+        assert!(registry.acquire(Db::InMemory, Some("missing")).await.is_err());
+
+        // Only a failure of the connection drops the backend, the next command then connects again
+        let mut backend = registry.acquire(Db::InMemory, None).await?;
+        let failed = backend.get_pkg("missing").await;
+        assert!(backend.check(failed).is_err());
+        assert!(registry.acquire(Db::InMemory, None).await?.connect.is_zero());
+        let backend = registry.acquire(Db::InMemory, None).await?;
+        assert!(backend.check::<()>(Err(io::Error::from(io::ErrorKind::ConnectionReset).into())).is_err());
+        assert!(!registry.acquire(Db::InMemory, None).await?.connect.is_zero());
+        Ok(())
+    }
+}
@@ -0,0 +1,38 @@
+use std::{collections::BTreeSet, cmp::Ordering::Equal};
+
+use anyhow::{Result, bail};
+
+/// Lowercase words of the text, anything but letters and digits separates them.
+pub fn tokenize(text: &str) -> impl Iterator<Item = String> + '_ {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .map(str::to_lowercase)
+}
+
+/// Words a package can be found by: those of its name, description and keywords.
+pub fn pkg_words(name: &str, description: &str, keywords: Option<&str>) -> BTreeSet<String> {
+    tokenize(name)
+        .chain(tokenize(description))
+        .chain(keywords.into_iter().flat_map(tokenize))
+        .collect()
+}
+
+/// Words of a search query, without repetitions.
+pub fn query_words(query: &str) -> Result<Vec<String>> {
+    let words: BTreeSet<String> = tokenize(query).collect();
+    if words.is_empty() {
+        bail!("Search query has no words");
+    }
+    Ok(words.into_iter().collect())
+}
+
+/// A package matches when every query word is a prefix of one of its words, whole words included.
+pub fn matches(words: &BTreeSet<String>, query: &[String]) -> bool {
+    query.iter().all(|prefix| words.range(prefix.clone()..).next().is_some_and(|word| word.starts_with(prefix.as_str())))
+}
+
+/// Orders matched packages by popularity, most popular first with ties broken by name, and keeps the first `limit`.
+pub fn rank(mut hits: Vec<(String, f32)>, limit: u32) -> Vec<String> {
+    hits.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap_or(Equal).then_with(|| a.0.cmp(&b.0)));
+    hits.into_iter().take(limit as usize).map(|(name, _)| name).collect()
+}
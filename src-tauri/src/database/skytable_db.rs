@@ -1,14 +1,13 @@
-use std::{time::{Instant, Duration}, collections::HashMap};
+use std::{time::{Instant, Duration}, collections::{HashMap, HashSet}, cmp::{Ordering, Ordering::Equal}};
 
 use serde::{Deserialize, Serialize};
-use skytable::{Query, Connection, actions::Actions, ddl::Ddl, types::{IntoSkyhashBytes, FromSkyhashBytes}, SkyResult};
+use skytable::{Query, Connection, Pipeline, Element, RespCode, actions::Actions, ddl::Ddl, types::{IntoSkyhashBytes, FromSkyhashBytes}, SkyResult};
 use anyhow::{Result, Ok, bail};
 use tauri::regex::internal::Inst;
-use crate::models::{Comment, AdditionalPackageData, PackageDependency, BasicPackageData, PackageData};
+use crate::{models::{Comment, AdditionalPackageData, PackageDependency, BasicPackageData, BasicPackagePatch, CatalogStats, PackageData}, config::ConnectionProfile};
 use async_trait::async_trait;
-use std::cmp::Ordering::Equal;
 
-use super::{DbActions, DbResponse};
+use super::{DbActions, DbResponse, Connect, PhaseTimer, sort_values_by, search, stats::Aggregates};
 
 #[derive(Debug, Serialize, Deserialize)]
 struct Comments {
@@ -20,29 +19,426 @@ struct Dependencies {
     data: Vec<PackageDependency>
 }
 
+// Entry of the list kept under a dependency name, telling which package depends on it and in which group
+#[derive(Debug, Serialize, Deserialize)]
+struct ReverseDependency {
+    group: String,
+    pkg: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct ReverseDependencies {
+    data: Vec<ReverseDependency>
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq, PartialOrd)]
+#[serde(untagged)]
+enum RankValue {
+    Number(f64),
+    Text(String),
+}
+
+// Entry of an ordering list; lists are sorted by value and then name, both descending, like `sort_values_by` reversed
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+struct RankEntry {
+    value: RankValue,
+    name: String,
+}
+
+impl RankEntry {
+    fn cmp_rank(&self, other: &Self) -> Ordering {
+        self.value.partial_cmp(&other.value).unwrap_or(Equal).then_with(|| self.name.cmp(&other.name))
+    }
+}
+
+// The client is synchronous and decodes replies while reading them, so requests are timed as a whole; helpers send
+// everything through `request`, which lets each operation report all round-trips it made, table switches included
 pub struct SkytableClient {
-    connection: Connection
+    connection: Connection,
+    // Requests sent since the last time a response took the count, `USE` switching the table included
+    requests: u32,
 }
 
 const BASIC_PKGS_TABLE: &str = "pkgs:basic";
 const ADDITIONAL_PKGS_TABLE: &str = "pkgs:additional";
 const COMMENTS_TABLE: &str = "pkgs:comments";
 const DEPENDENCIES_TABLE: &str = "pkgs:dependencies";
+const REVERSE_DEPENDENCIES_TABLE: &str = "pkgs:rdeps";
+// One key per package whose dependencies are in `REVERSE_DEPENDENCIES_TABLE`
+const INDEXED_DEPENDENCIES_TABLE: &str = "pkgs:rdeps_indexed";
+// Names of the packages of every maintainer and submitter
+const MAINTAINERS_TABLE: &str = "pkgs:maintainers";
+const SUBMITTERS_TABLE: &str = "pkgs:submitters";
+// One list per field in `RANKED_FIELDS` holding every package in descending order, so a top-N is a single `LGET RANGE`
+const RANKS_TABLE: &str = "pkgs:ranks";
+// Other fields are sorted on the client after reading every package
+const RANKED_FIELDS: [&str; 3] = ["votes", "popularity", "last_updated"];
 
 impl SkytableClient {
-    pub fn try_new() -> Result<Self> {
-        Ok(SkytableClient { 
-            connection: Connection::new("127.0.0.1", 2003)?
-        })
+    pub fn try_new(profile: &ConnectionProfile) -> Result<Self> {
+        if profile.tls {
+            bail!("TLS connections are not supported for Skytable");
+        }
+        let mut connection = Connection::new(&profile.host, profile.port)?;
+        // Unlike the package tables this one is not created by the scraper; an "already exists" response is fine
+        connection.run_query_raw(Query::new().arg("CREATE").arg("TABLE").arg(REVERSE_DEPENDENCIES_TABLE).arg("keymap(str,list<binstr>)"))?;
+        connection.run_query_raw(Query::new().arg("CREATE").arg("TABLE").arg(INDEXED_DEPENDENCIES_TABLE).arg("keymap(str,str)"))?;
+        for table in [MAINTAINERS_TABLE, SUBMITTERS_TABLE] {
+            connection.run_query_raw(Query::new().arg("CREATE").arg("TABLE").arg(table).arg("keymap(str,list<str>)"))?;
+        }
+        connection.run_query_raw(Query::new().arg("CREATE").arg("TABLE").arg(RANKS_TABLE).arg("keymap(str,list<binstr>)"))?;
+        connection.switch(RANKS_TABLE)?;
+        for field in RANKED_FIELDS {
+            connection.run_query_raw(Query::new().arg("LSET").arg(field))?;
+        }
+        Ok(SkytableClient { connection, requests: 0 })
+    }
+
+    // Every request of an operation goes through here, so its response can tell how many round-trips were made
+    fn request<T>(&mut self, send: impl FnOnce(&mut Connection) -> SkyResult<T>) -> Result<T> {
+        self.requests += 1;
+        Ok(send(&mut self.connection)?)
+    }
+
+    fn take_requests(&mut self) -> u32 {
+        std::mem::take(&mut self.requests)
     }
 
     fn get_all_basic_package_data(&mut self) -> Result<Vec<BasicPackageData>> {
-        self.connection.switch(BASIC_PKGS_TABLE)?;
-        let count = self.connection.dbsize()?;
-        let keys: Vec<String> = self.connection.lskeys(count)?;
-        let response: DbResponse<Vec<BasicPackageData>> = self.connection.mget(keys)?;
+        self.request(|c| c.switch(BASIC_PKGS_TABLE))?;
+        let count = self.request(|c| c.dbsize())?;
+        let keys: Vec<String> = self.request(|c| c.lskeys(count))?;
+        let response: DbResponse<Vec<BasicPackageData>> = self.request(|c| c.mget(keys))?;
         Ok(response.result)
     }
+
+    fn table_keys(&mut self, table: &str) -> Result<HashSet<String>> {
+        self.request(|c| c.switch(table))?;
+        let count = self.request(|c| c.dbsize())?;
+        let keys: Vec<String> = self.request(|c| c.lskeys(count))?;
+        Ok(keys.into_iter().collect())
+    }
+
+    // Packages written by other clients, such as the scraper, are missing from the reverse dependency lists; once the
+    // number of indexed packages no longer matches the package count, the dependency lists of the missing ones are
+    // read and indexed, and packages deleted by others are forgotten
+    fn ensure_reverse_dependencies(&mut self) -> Result<()> {
+        self.request(|c| c.switch(BASIC_PKGS_TABLE))?;
+        let count = self.request(|c| c.dbsize())?;
+        self.request(|c| c.switch(INDEXED_DEPENDENCIES_TABLE))?;
+        if self.request(|c| c.dbsize())? == count {
+            return Ok(());
+        }
+        let indexed = self.table_keys(INDEXED_DEPENDENCIES_TABLE)?;
+        let names = self.table_keys(BASIC_PKGS_TABLE)?;
+        let missing: Vec<&String> = names.difference(&indexed).collect();
+
+        let lists = self.run_pipeline(DEPENDENCIES_TABLE, missing.iter().map(|name| Query::new().arg("LGET").arg(*name)).collect())?;
+        let mut queries = vec![];
+        for (name, element) in missing.iter().zip(lists) {
+            if element == Element::RespCode(RespCode::NotFound) {
+                continue;
+            }
+            queries.extend(reverse_dependency_queries(name, &Dependencies::from_element(element)?.data));
+        }
+        self.run_pipeline(REVERSE_DEPENDENCIES_TABLE, queries)?;
+
+        let marks = missing.iter().map(|name| indexed_query(name))
+            .chain(indexed.difference(&names).map(|name| Query::new().arg("DEL").arg(name)))
+            .collect();
+        self.run_pipeline(INDEXED_DEPENDENCIES_TABLE, marks)?;
+        Ok(())
+    }
+
+    fn reverse_dependency_entries(&mut self, dep: &str) -> Result<Vec<ReverseDependency>> {
+        self.request(|c| c.switch(REVERSE_DEPENDENCIES_TABLE))?;
+        let element = self.request(|c| c.run_query_raw(Query::new().arg("LGET").arg(dep)))?;
+        if element == Element::RespCode(RespCode::NotFound) {
+            return Ok(vec![]);
+        }
+        Ok(ReverseDependencies::from_element(element)?.data)
+    }
+
+    // Removes the package from the reverse dependency lists of everything it depends on
+    fn unindex_dependencies(&mut self, pkg_name: &str) -> Result<()> {
+        self.request(|c| c.switch(DEPENDENCIES_TABLE))?;
+        let element = self.request(|c| c.run_query_raw(Query::new().arg("LGET").arg(pkg_name)))?;
+        if element == Element::RespCode(RespCode::NotFound) {
+            return Ok(());
+        }
+        let dependencies = Dependencies::from_element(element)?;
+        let mut deps: Vec<&String> = dependencies.data.iter().flat_map(|d| &d.packages).collect();
+        deps.sort();
+        deps.dedup();
+
+        for dep in deps {
+            let entries = self.reverse_dependency_entries(dep)?;
+            if !entries.iter().any(|entry| entry.pkg == pkg_name) {
+                continue;
+            }
+            self.request(|c| c.run_query_raw(Query::new().arg("LMOD").arg(dep).arg("CLEAR")))?;
+            let kept: Vec<&ReverseDependency> = entries.iter().filter(|entry| entry.pkg != pkg_name).collect();
+            if !kept.is_empty() {
+                let query = kept.into_iter().fold(Query::new().arg("LMOD").arg(dep).arg("PUSH"), |query, entry| query.arg(entry));
+                self.request(|c| c.run_query_raw(query))?;
+            }
+        }
+        Ok(())
+    }
+
+    fn owned_pkgs(&mut self, table: &str, owner: &str) -> Result<Vec<String>> {
+        self.request(|c| c.switch(table))?;
+        let element = self.request(|c| c.run_query_raw(Query::new().arg("LGET").arg(owner)))?;
+        if element == Element::RespCode(RespCode::NotFound) {
+            return Ok(vec![]);
+        }
+        Ok(Vec::<String>::from_element(element)?)
+    }
+
+    // Removes the package from the list of its maintainer or submitter in `table`
+    fn unindex_owner(&mut self, table: &str, owner: &str, pkg_name: &str) -> Result<()> {
+        let names = self.owned_pkgs(table, owner)?;
+        if !names.iter().any(|name| name == pkg_name) {
+            return Ok(());
+        }
+        self.request(|c| c.run_query_raw(Query::new().arg("LMOD").arg(owner).arg("CLEAR")))?;
+        let kept: Vec<&String> = names.iter().filter(|name| *name != pkg_name).collect();
+        if !kept.is_empty() {
+            let query = kept.into_iter().fold(Query::new().arg("LMOD").arg(owner).arg("PUSH"), |query, name| query.arg(name));
+            self.request(|c| c.run_query_raw(query))?;
+        }
+        Ok(())
+    }
+
+    // The rank helpers expect `RANKS_TABLE` to be selected, operations switch to it once before using them
+    fn rank_len(&mut self, field: &str) -> Result<usize> {
+        let element = self.request(|c| c.run_query_raw(Query::new().arg("LGET").arg(field).arg("LEN")))?;
+        Ok(match element {
+            Element::UnsignedInt(len) => len as usize,
+            _ => 0,
+        })
+    }
+
+    fn rank_entry_at(&mut self, field: &str, idx: usize) -> Result<RankEntry> {
+        let element = self.request(|c| c.run_query_raw(Query::new().arg("LGET").arg(field).arg("VALUEAT").arg(idx.to_string())))?;
+        Ok(RankEntry::from_element(element)?)
+    }
+
+    // Index of the first entry of the `field` list not ranked above each of `entries`, together with the list length.
+    // The entries are bisected side by side, every step reads the middle of each pending range in a single pipeline,
+    // so a batch costs about log2(len) + 1 round-trips like a single entry
+    fn rank_positions(&mut self, field: &str, entries: &[RankEntry]) -> Result<(Vec<usize>, usize)> {
+        let len = self.rank_len(field)?;
+        let mut ranges = vec![(0, len); entries.len()];
+        loop {
+            let pending: Vec<usize> = (0..ranges.len()).filter(|&idx| ranges[idx].0 < ranges[idx].1).collect();
+            if pending.is_empty() {
+                break;
+            }
+            let mut pipeline = Pipeline::new();
+            for &idx in &pending {
+                let mid = (ranges[idx].0 + ranges[idx].1) / 2;
+                pipeline.push(Query::new().arg("LGET").arg(field).arg("VALUEAT").arg(mid.to_string()));
+            }
+            let elements = self.request(|c| c.run_pipeline(pipeline))?;
+            for (idx, element) in pending.into_iter().zip(elements) {
+                let (low, high) = ranges[idx];
+                let mid = (low + high) / 2;
+                if RankEntry::from_element(element)?.cmp_rank(&entries[idx]) == Ordering::Greater {
+                    ranges[idx] = (mid + 1, high);
+                } else {
+                    ranges[idx] = (low, mid);
+                }
+            }
+        }
+        Ok((ranges.into_iter().map(|(low, _)| low).collect(), len))
+    }
+
+    // Inserts entries sorted in descending order at their positions, leaving the entries already listed untouched
+    fn add_ranks(&mut self, field: &str, entries: &[RankEntry]) -> Result<()> {
+        if entries.is_empty() {
+            return Ok(());
+        }
+        let (positions, len) = self.rank_positions(field, entries)?;
+        let mut pipeline = Pipeline::new();
+        // Each entry lands below the ones of the batch inserted before it, which shift its position by their count
+        for (inserted, (position, entry)) in positions.into_iter().zip(entries).enumerate() {
+            let query = match position == len {
+                true => Query::new().arg("LMOD").arg(field).arg("PUSH").arg(entry),
+                false => Query::new().arg("LMOD").arg(field).arg("INSERT").arg((position + inserted).to_string()).arg(entry),
+            };
+            pipeline.push(query);
+        }
+        self.request(|c| c.run_pipeline(pipeline))?;
+        Ok(())
+    }
+
+    fn remove_rank(&mut self, field: &str, entry: &RankEntry) -> Result<()> {
+        let (positions, len) = self.rank_positions(field, std::slice::from_ref(entry))?;
+        let position = positions[0];
+        if position < len && self.rank_entry_at(field, position)? == *entry {
+            self.request(|c| c.run_query_raw(Query::new().arg("LMOD").arg(field).arg("REMOVE").arg(position.to_string())))?;
+        }
+        Ok(())
+    }
+
+    // Moves the package between the positions of its old and new values in every list where its value changed
+    fn rerank(&mut self, old: &BasicPackageData, new: &BasicPackageData) -> Result<()> {
+        let changed: Vec<_> = rank_entries(old).into_iter().zip(rank_entries(new)).filter(|((_, old), (_, new))| old != new).collect();
+        if changed.is_empty() {
+            return Ok(());
+        }
+        self.request(|c| c.switch(RANKS_TABLE))?;
+        for ((field, old), (_, new)) in changed {
+            self.remove_rank(field, &old)?;
+            self.add_ranks(field, &[new])?;
+        }
+        Ok(())
+    }
+
+    // Replaces the `field` list with the entries, sorted here
+    fn write_ranks(&mut self, field: &str, mut entries: Vec<RankEntry>) -> Result<()> {
+        entries.sort_by(|a, b| b.cmp_rank(a));
+        let mut pipeline = Pipeline::new();
+        pipeline.push(Query::new().arg("LSET").arg(field));
+        pipeline.push(Query::new().arg("LMOD").arg(field).arg("CLEAR"));
+        if !entries.is_empty() {
+            pipeline.push(entries.iter().fold(Query::new().arg("LMOD").arg(field).arg("PUSH"), |query, entry| query.arg(entry)));
+        }
+        self.request(|c| c.run_pipeline(pipeline))?;
+        Ok(())
+    }
+
+    // Packages written by other clients, such as the scraper, have no entries; the lists are rebuilt from a full scan
+    // once their length no longer matches the package count. Leaves `RANKS_TABLE` selected
+    fn ensure_ranks(&mut self) -> Result<()> {
+        self.request(|c| c.switch(BASIC_PKGS_TABLE))?;
+        let count = self.request(|c| c.dbsize())? as usize;
+        self.request(|c| c.switch(RANKS_TABLE))?;
+        let mut in_sync = true;
+        for field in RANKED_FIELDS {
+            in_sync &= self.rank_len(field)? == count;
+        }
+        if in_sync {
+            return Ok(());
+        }
+        let basics = self.get_all_basic_package_data()?;
+        let mut lists: HashMap<&str, Vec<RankEntry>> = HashMap::new();
+        for (field, entry) in basics.iter().flat_map(rank_entries) {
+            lists.entry(field).or_default().push(entry);
+        }
+        self.request(|c| c.switch(RANKS_TABLE))?;
+        for field in RANKED_FIELDS {
+            self.write_ranks(field, lists.remove(field).unwrap_or_default())?;
+        }
+        Ok(())
+    }
+
+    // Names at the positions `limit_start..limit_end` of the `field` list
+    fn ranked_names(&mut self, field: &str, limit_start: u32, limit_end: u32) -> Result<Vec<String>> {
+        self.ensure_ranks()?;
+        let end = (limit_end as usize).min(self.rank_len(field)?);
+        let start = limit_start as usize;
+        if start >= end {
+            return Ok(vec![]);
+        }
+        let element = self.request(|c| c.run_query_raw(
+            Query::new().arg("LGET").arg(field).arg("RANGE").arg(start.to_string()).arg(end.to_string())
+        ))?;
+        Ok(parse_rank_entries(element)?.into_iter().map(|entry| entry.name).collect())
+    }
+
+    // Sends all queries meant for one table in a single pipeline
+    // Removes the package from every table, together with its entries in the owner, reverse dependency and rank lists
+    fn remove_pkg(&mut self, pkg_name: &str) -> Result<()> {
+        self.unindex_dependencies(pkg_name)?;
+        self.request(|c| c.switch(BASIC_PKGS_TABLE))?;
+        if let Some(basic) = self.request(|c| c.get::<BasicPackageData>(pkg_name)).ok() {
+            self.unindex_owner(MAINTAINERS_TABLE, &basic.maintainer, pkg_name)?;
+            self.request(|c| c.switch(RANKS_TABLE))?;
+            for (field, entry) in rank_entries(&basic) {
+                self.remove_rank(field, &entry)?;
+            }
+        }
+        self.request(|c| c.switch(ADDITIONAL_PKGS_TABLE))?;
+        if let Some(additional) = self.request(|c| c.get::<AdditionalPackageData>(pkg_name)).ok() {
+            self.unindex_owner(SUBMITTERS_TABLE, &additional.submitter, pkg_name)?;
+        }
+        for table in [BASIC_PKGS_TABLE, ADDITIONAL_PKGS_TABLE, COMMENTS_TABLE, DEPENDENCIES_TABLE, INDEXED_DEPENDENCIES_TABLE] {
+            self.request(|c| c.switch(table))?;
+            self.request(|c| c.del(pkg_name))?;
+        }
+        Ok(())
+    }
+
+    // SET fails on existing keys, so inserting replaces a package by removing it first. Which of the packages exist is
+    // found with a single pipeline, only those pay for the removal
+    fn remove_existing_pkgs(&mut self, pkg_names: &[&str]) -> Result<()> {
+        let responses = self.run_pipeline(BASIC_PKGS_TABLE, pkg_names.iter().map(|name| Query::new().arg("EXISTS").arg(*name)).collect())?;
+        for (name, element) in pkg_names.iter().zip(responses) {
+            match element {
+                Element::UnsignedInt(0) => {},
+                Element::UnsignedInt(_) => self.remove_pkg(name)?,
+                other => bail!("Unexpected response to EXISTS: {:?}", other),
+            }
+        }
+        Ok(())
+    }
+
+    fn run_pipeline(&mut self, table: &str, queries: Vec<Query>) -> Result<Vec<Element>> {
+        if queries.is_empty() {
+            return Ok(vec![]);
+        }
+        self.request(|c| c.switch(table))?;
+        let mut pipeline = Pipeline::new();
+        queries.into_iter().for_each(|query| pipeline.push(query));
+        self.request(|c| c.run_pipeline(pipeline))
+    }
+}
+
+fn reverse_dependency_queries(pkg_name: &str, dependencies: &[PackageDependency]) -> Vec<Query> {
+    let mut queries = vec![];
+    for dependency in dependencies {
+        for dep in &dependency.packages {
+            let entry = ReverseDependency { group: dependency.group.clone(), pkg: pkg_name.to_owned() };
+            queries.push(Query::new().arg("LSET").arg(dep));
+            queries.push(Query::new().arg("LMOD").arg(dep).arg("PUSH").arg(&entry));
+        }
+    }
+    queries
+}
+
+// Entries of the package in the list of every field in `RANKED_FIELDS`
+fn rank_entries(basic: &BasicPackageData) -> [(&'static str, RankEntry); 3] {
+    let entry = |value| RankEntry { value, name: basic.name.clone() };
+    [
+        ("votes", entry(RankValue::Number(basic.votes as f64))),
+        ("popularity", entry(RankValue::Number(basic.popularity as f64))),
+        ("last_updated", entry(RankValue::Text(basic.last_updated.clone()))),
+    ]
+}
+
+fn parse_rank_entries(element: Element) -> Result<Vec<RankEntry>> {
+    let entries_bytes: Vec<Vec<u8>> = element.try_element_into()?;
+    entries_bytes.iter()
+        .map(|bytes| Ok(serde_json::from_slice(bytes)?))
+        .collect()
+}
+
+// Marks the package as having its dependencies in `REVERSE_DEPENDENCIES_TABLE`, overwriting an earlier mark
+fn indexed_query(pkg_name: &str) -> Query {
+    Query::new().arg("USET").arg(pkg_name).arg("1")
+}
+
+fn owner_queries(owner: &str, pkg_name: &str) -> [Query; 2] {
+    [Query::new().arg("LSET").arg(owner), Query::new().arg("LMOD").arg(owner).arg("PUSH").arg(pkg_name)]
+}
+
+#[async_trait]
+impl Connect for SkytableClient {
+    async fn connect(profile: &ConnectionProfile) -> Result<Self> {
+        Self::try_new(profile)
+    }
 }
 
 #[async_trait]
@@ -60,141 +456,373 @@ impl DbActions for SkytableClient {
     async fn run_custom_query(&mut self, query: &str) -> Result<DbResponse<String>> {
         let parts: Vec<&str> = query.split(" ").collect();
 
-        let start = Instant::now();
-        let respone = self.connection.run_query_raw(Query::from(parts))?;
-        let duration = start.elapsed();
+        let mut timer = PhaseTimer::start();
+        let respone = self.request(|c| c.run_query_raw(Query::from(parts)))?;
+        timer.round_trips(self.take_requests());
 
         let result = respone.try_element_into()?;
-        Ok(DbResponse { result, duration })
+        timer.deserialization();
+        Ok(timer.respond(result))
     }
     
     async fn sort_pkgs_by_field_with_limit(&mut self, field: &str, limit_start: u32, limit_end: u32) -> Result<DbResponse<Vec<String>>> {
-        let start = Instant::now();        
+        let mut timer = PhaseTimer::start();
+        if RANKED_FIELDS.contains(&field) {
+            let result = self.ranked_names(field, limit_start, limit_end)?;
+            timer.round_trips(self.take_requests());
+            return Ok(timer.respond(result));
+        }
         let mut packages = self.get_all_basic_package_data()?;
+        timer.round_trips(self.take_requests());
 
         sort_values_by(&mut packages, field)?;
         
         let result: Vec<String> = packages.iter().rev()
             .skip(limit_start as usize)
-            .take((limit_end - limit_start) as usize)
+            .take(limit_end.saturating_sub(limit_start) as usize)
             .map(|v| v.name.clone())
             .collect();
-        let duration = start.elapsed();
-        Ok(DbResponse { result, duration })
+        Ok(timer.respond(result))
     }
     async fn get_most_voted_pkgs(&mut self, number: u32) -> Result<DbResponse<Vec<BasicPackageData>>> {
-        let start = Instant::now();        
-        let mut packages = self.get_all_basic_package_data()?;
-
-        sort_values_by(&mut packages, "votes")?;
-        let result: Vec<BasicPackageData> = packages.into_iter().rev()
-            .take(number as usize)
-            .collect();
-        let duration = start.elapsed();
-        Ok(DbResponse { result, duration })
+        let mut timer = PhaseTimer::start();
+        let names = self.ranked_names("votes", 0, number)?;
+        let result = match names.is_empty() {
+            true => vec![],
+            false => {
+                self.request(|c| c.switch(BASIC_PKGS_TABLE))?;
+                let response: DbResponse<Vec<BasicPackageData>> = self.request(|c| c.mget(names))?;
+                response.result
+            },
+        };
+        timer.round_trips(self.take_requests());
+        Ok(timer.respond(result))
     }
 
     async fn insert_pkg(&mut self, pkg: &PackageData) -> Result<DbResponse<()>> {
-        let start = Instant::now();
+        let mut timer = PhaseTimer::start();
         let pkg_name = pkg.basic.name.clone();
-        self.connection.switch(BASIC_PKGS_TABLE)?;
-        self.connection.set(&pkg_name, &pkg.basic)?;
+        self.remove_existing_pkgs(&[&pkg_name])?;
+        self.request(|c| c.switch(BASIC_PKGS_TABLE))?;
+        self.request(|c| c.set(&pkg_name, &pkg.basic))?;
 
-        self.connection.switch(ADDITIONAL_PKGS_TABLE)?;
-        self.connection.set(&pkg_name, &pkg.additional)?;
+        self.request(|c| c.switch(ADDITIONAL_PKGS_TABLE))?;
+        self.request(|c| c.set(&pkg_name, &pkg.additional))?;
 
-        self.connection.switch(COMMENTS_TABLE)?;
-        self.connection.run_query_raw(Query::new().arg("LSET").arg(&pkg.basic.name))?;
-        self.connection.run_query_raw(Query::new().arg("LMOD").arg(&pkg.basic.name).arg("CLEAR"))?;
+        self.request(|c| c.switch(COMMENTS_TABLE))?;
+        self.request(|c| c.run_query_raw(Query::new().arg("LSET").arg(&pkg.basic.name)))?;
+        self.request(|c| c.run_query_raw(Query::new().arg("LMOD").arg(&pkg.basic.name).arg("CLEAR")))?;
 
         for comment in &pkg.comments {
             let query = Query::new().arg("LMOD").arg(&pkg.basic.name).arg("PUSH").arg(comment);
-            self.connection.run_query_raw(query)?;
+            self.request(|c| c.run_query_raw(query))?;
         }
 
-        self.connection.switch(DEPENDENCIES_TABLE)?;
-        self.connection.run_query_raw(Query::new().arg("LSET").arg(&pkg.basic.name))?;
-        self.connection.run_query_raw(Query::new().arg("LMOD").arg(&pkg.basic.name).arg("CLEAR"))?;
+        self.request(|c| c.switch(DEPENDENCIES_TABLE))?;
+        self.request(|c| c.run_query_raw(Query::new().arg("LSET").arg(&pkg.basic.name)))?;
+        self.request(|c| c.run_query_raw(Query::new().arg("LMOD").arg(&pkg.basic.name).arg("CLEAR")))?;
 
         for dependency in &pkg.dependencies {
             let query = Query::new().arg("LMOD").arg(&pkg.basic.name).arg("PUSH").arg(dependency);
-            self.connection.run_query_raw(query)?;
+            self.request(|c| c.run_query_raw(query))?;
         }
 
-        let duration = start.elapsed();
-        Ok(DbResponse { result: (), duration })
+        self.request(|c| c.switch(REVERSE_DEPENDENCIES_TABLE))?;
+        for query in reverse_dependency_queries(&pkg_name, &pkg.dependencies) {
+            self.request(|c| c.run_query_raw(query))?;
+        }
+        self.request(|c| c.switch(INDEXED_DEPENDENCIES_TABLE))?;
+        self.request(|c| c.run_query_raw(indexed_query(&pkg_name)))?;
+
+        for (table, owner) in [(MAINTAINERS_TABLE, &pkg.basic.maintainer), (SUBMITTERS_TABLE, &pkg.additional.submitter)] {
+            self.request(|c| c.switch(table))?;
+            for query in owner_queries(owner, &pkg_name) {
+                self.request(|c| c.run_query_raw(query))?;
+            }
+        }
+
+        self.request(|c| c.switch(RANKS_TABLE))?;
+        for (field, entry) in rank_entries(&pkg.basic) {
+            self.add_ranks(field, &[entry])?;
+        }
+
+        timer.round_trips(self.take_requests());
+        Ok(timer.respond(()))
+    }
+
+    async fn insert_pkgs(&mut self, pkgs: &[PackageData]) -> Result<DbResponse<()>> {
+        let mut timer = PhaseTimer::start();
+        // A package listed twice is written once, with its last data, which is what replacing it would leave
+        let mut names = HashSet::new();
+        let mut pkgs: Vec<&PackageData> = pkgs.iter().rev().filter(|pkg| names.insert(pkg.basic.name.as_str())).collect();
+        pkgs.reverse();
+        let pkg_names: Vec<&str> = pkgs.iter().map(|pkg| pkg.basic.name.as_str()).collect();
+        timer.processing();
+        self.remove_existing_pkgs(&pkg_names)?;
+        timer.round_trips(self.take_requests());
+
+        let basic = pkgs.iter().map(|pkg| Query::new().arg("SET").arg(&pkg.basic.name).arg(&pkg.basic)).collect();
+        let additional = pkgs.iter().map(|pkg| Query::new().arg("SET").arg(&pkg.basic.name).arg(&pkg.additional)).collect();
+        for (table, queries) in [(BASIC_PKGS_TABLE, basic), (ADDITIONAL_PKGS_TABLE, additional)] {
+            let responses = self.run_pipeline(table, queries)?;
+            if let Some((idx, code)) = responses.iter().enumerate().find(|(_, r)| **r != Element::RespCode(RespCode::Okay)) {
+                bail!("Cannot insert {} into {}: {:?}", pkgs[idx].basic.name, table, code);
+            }
+        }
+
+        // Lists are reset the same way as in `insert_pkg`, then filled with a single push per package
+        let mut comments = vec![];
+        let mut dependencies = vec![];
+        for pkg in pkgs {
+            let name = &pkg.basic.name;
+            for queries in [&mut comments, &mut dependencies] {
+                queries.push(Query::new().arg("LSET").arg(name));
+                queries.push(Query::new().arg("LMOD").arg(name).arg("CLEAR"));
+            }
+            if !pkg.comments.is_empty() {
+                let query = pkg.comments.iter().fold(Query::new().arg("LMOD").arg(name).arg("PUSH"), |query, c| query.arg(c));
+                comments.push(query);
+            }
+            if !pkg.dependencies.is_empty() {
+                let query = pkg.dependencies.iter().fold(Query::new().arg("LMOD").arg(name).arg("PUSH"), |query, d| query.arg(d));
+                dependencies.push(query);
+            }
+        }
+        self.run_pipeline(COMMENTS_TABLE, comments)?;
+        self.run_pipeline(DEPENDENCIES_TABLE, dependencies)?;
+        self.run_pipeline(REVERSE_DEPENDENCIES_TABLE, pkgs.iter().flat_map(|pkg| reverse_dependency_queries(&pkg.basic.name, &pkg.dependencies)).collect())?;
+        self.run_pipeline(INDEXED_DEPENDENCIES_TABLE, pkgs.iter().map(|pkg| indexed_query(&pkg.basic.name)).collect())?;
+        self.run_pipeline(MAINTAINERS_TABLE, pkgs.iter().flat_map(|pkg| owner_queries(&pkg.basic.maintainer, &pkg.basic.name)).collect())?;
+        self.run_pipeline(SUBMITTERS_TABLE, pkgs.iter().flat_map(|pkg| owner_queries(&pkg.additional.submitter, &pkg.basic.name)).collect())?;
+
+        let mut added: HashMap<&str, Vec<RankEntry>> = HashMap::new();
+        for (field, entry) in pkgs.iter().flat_map(|pkg| rank_entries(&pkg.basic)) {
+            added.entry(field).or_default().push(entry);
+        }
+        self.request(|c| c.switch(RANKS_TABLE))?;
+        for field in RANKED_FIELDS {
+            let mut entries = added.remove(field).unwrap_or_default();
+            entries.sort_by(|a, b| b.cmp_rank(a));
+            self.add_ranks(field, &entries)?;
+        }
+
+        timer.round_trips(self.take_requests());
+        Ok(timer.respond(()))
     }
 
     async fn get_pkg(&mut self, name: &str) -> Result<DbResponse<PackageData>> {
-        let start = Instant::now();
-        self.connection.switch(BASIC_PKGS_TABLE)?;
-        let basic: BasicPackageData = self.connection.get(name)?;
+        let mut timer = PhaseTimer::start();
+        self.request(|c| c.switch(BASIC_PKGS_TABLE))?;
+        let basic: BasicPackageData = self.request(|c| c.get(name))?;
 
-        self.connection.switch(ADDITIONAL_PKGS_TABLE)?;
-        let additional: AdditionalPackageData = self.connection.get(name)?;
+        self.request(|c| c.switch(ADDITIONAL_PKGS_TABLE))?;
+        let additional: AdditionalPackageData = self.request(|c| c.get(name))?;
 
-        self.connection.switch(COMMENTS_TABLE)?;
-        let comments: Comments = self.connection.run_query(Query::new().arg("LGET").arg(name))?;
+        self.request(|c| c.switch(COMMENTS_TABLE))?;
+        let comments: Comments = self.request(|c| c.run_query(Query::new().arg("LGET").arg(name)))?;
 
-        self.connection.switch(DEPENDENCIES_TABLE)?;
-        let dependencies: Dependencies = self.connection.run_query(Query::new().arg("LGET").arg(name))?;
+        self.request(|c| c.switch(DEPENDENCIES_TABLE))?;
+        let dependencies: Dependencies = self.request(|c| c.run_query(Query::new().arg("LGET").arg(name)))?;
+        timer.round_trips(self.take_requests());
 
-        let duration = start.elapsed();
         let result = PackageData {
             basic,
             additional,
             comments: comments.data,
             dependencies: dependencies.data,
         };
-        Ok(DbResponse { result, duration })
+        Ok(timer.respond(result))
+    }
+
+    async fn contains_pkg(&mut self, pkg_name: &str) -> Result<DbResponse<bool>> {
+        let mut timer = PhaseTimer::start();
+        self.request(|c| c.switch(BASIC_PKGS_TABLE))?;
+        let element = self.request(|c| c.run_query_raw(Query::new().arg("EXISTS").arg(pkg_name)))?;
+        let result = match element {
+            Element::UnsignedInt(count) => count > 0,
+            other => bail!("Unexpected response to EXISTS: {:?}", other),
+        };
+        timer.round_trips(self.take_requests());
+        Ok(timer.respond(result))
     }
 
     async fn remove_comments(&mut self, pkg_name: &str) -> Result<DbResponse<()>> {
-        let start = Instant::now();
-        self.connection.switch(COMMENTS_TABLE)?;
-        self.connection.run_query_raw(Query::new().arg("LMOD").arg(pkg_name).arg("CLEAR"))?;
-        let duration = start.elapsed();
-        Ok(DbResponse { result: (), duration })
+        let mut timer = PhaseTimer::start();
+        self.request(|c| c.switch(COMMENTS_TABLE))?;
+        self.request(|c| c.run_query_raw(Query::new().arg("LMOD").arg(pkg_name).arg("CLEAR")))?;
+        timer.round_trips(self.take_requests());
+        Ok(timer.respond(()))
     }
 
-    async fn get_packages_occurences_in_deps(&mut self, pkg_names: &Vec<String>) -> Result<DbResponse<HashMap<String, u32>>> {
-        let mut result: HashMap<String, u32> = HashMap::new();
-        pkg_names.iter().for_each(|name| _ = result.insert(name.to_owned(), 0));
+    async fn delete_pkg(&mut self, pkg_name: &str) -> Result<DbResponse<()>> {
+        let mut timer = PhaseTimer::start();
+        self.remove_pkg(pkg_name)?;
+        timer.round_trips(self.take_requests());
+        Ok(timer.respond(()))
+    }
 
-        self.connection.switch(DEPENDENCIES_TABLE)?;
-        let start = Instant::now();
-        let count = self.connection.dbsize()?;
-        let pkgs_deps_names: Vec<String> = self.connection.lskeys(count)?;
-
-        for pkg_name in pkgs_deps_names {
-            let dependencies: Dependencies = self.connection.run_query(Query::new().arg("LGET").arg(pkg_name))?;
-            for dep in dependencies.data {
-                if result.contains_key(&dep.group) {
-                    let count = result.get(&dep.group).unwrap() + 1;
-                    result.insert(dep.group, count);
+    async fn get_reverse_dependencies(&mut self, pkg_name: &str, groups: &[String]) -> Result<DbResponse<Vec<String>>> {
+        let mut timer = PhaseTimer::start();
+        self.ensure_reverse_dependencies()?;
+        let mut result: Vec<String> = self.reverse_dependency_entries(pkg_name)?
+            .into_iter()
+            .filter(|entry| groups.is_empty() || groups.contains(&entry.group))
+            .map(|entry| entry.pkg)
+            .collect();
+        result.sort();
+        result.dedup();
+        timer.round_trips(self.take_requests());
+        Ok(timer.respond(result))
+    }
+
+    async fn find_orphaned_keys(&mut self, remove: bool) -> Result<DbResponse<Vec<String>>> {
+        let mut timer = PhaseTimer::start();
+        self.request(|c| c.switch(BASIC_PKGS_TABLE))?;
+        let count = self.request(|c| c.dbsize())?;
+        let pkg_names: Vec<String> = self.request(|c| c.lskeys(count))?;
+        let pkg_names: HashSet<String> = pkg_names.into_iter().collect();
+
+        let mut result = vec![];
+        for table in [ADDITIONAL_PKGS_TABLE, COMMENTS_TABLE, DEPENDENCIES_TABLE] {
+            self.request(|c| c.switch(table))?;
+            let count = self.request(|c| c.dbsize())?;
+            let keys: Vec<String> = self.request(|c| c.lskeys(count))?;
+            for key in keys.into_iter().filter(|key| !pkg_names.contains(key)) {
+                if remove {
+                    self.request(|c| c.del(&key))?;
                 }
+                result.push(format!("{}:{}", table, key));
             }
         }
+        timer.round_trips(self.take_requests());
+        Ok(timer.respond(result))
+    }
 
-        let duration = start.elapsed();
-        Ok(DbResponse { result, duration })
+    // Skytable has no partial updates, so both operations read, change and write back the whole record without isolation
+    async fn search_pkgs(&mut self, query: &str, limit: u32) -> Result<DbResponse<Vec<String>>> {
+        let words = search::query_words(query)?;
+        let mut timer = PhaseTimer::start();
+        // No secondary indexes, so every package is read; keywords are kept with the additional data
+        let basics = self.get_all_basic_package_data()?;
+        timer.round_trips(self.take_requests());
+        let queries = basics.iter().map(|basic| Query::new().arg("GET").arg(&basic.name)).collect();
+        timer.processing();
+        let additional = self.run_pipeline(ADDITIONAL_PKGS_TABLE, queries)?;
+        timer.round_trips(self.take_requests());
+
+        let mut hits = vec![];
+        for (basic, element) in basics.into_iter().zip(additional) {
+            let keywords = match element {
+                Element::RespCode(RespCode::NotFound) => None,
+                element => AdditionalPackageData::from_element(element)?.keywords,
+            };
+            if search::matches(&search::pkg_words(&basic.name, &basic.description, keywords.as_deref()), &words) {
+                hits.push((basic.name, basic.popularity));
+            }
+        }
+        let result = search::rank(hits, limit);
+        Ok(timer.respond(result))
     }
-    
-}
 
-fn sort_values_by(data: &mut Vec<BasicPackageData>, key: &str) -> Result<()> {
-    match key {
-       "name" => data.sort_by_key(|k| k.name.clone()),
-       "version" => data.sort_by_key(|k| k.version.clone()),
-       "path_to_additional_data" => data.sort_by_key(|k| k.path_to_additional_data.clone()),
-       "votes" => data.sort_by_key(|k| k.votes),
-       "popularity" => data.sort_by(|a, b| a.popularity.partial_cmp(&b.popularity).unwrap_or(Equal)),
-       "description" => data.sort_by_key(|k| k.description.clone()),
-       "maintainer" => data.sort_by_key(|k| k.maintainer.clone()),
-       "last_updated" => data.sort_by_key(|k| k.last_updated.clone()),
-       _ => bail!("Unsuported field")
-    }
-    Ok(())
+    async fn get_pkgs_by_maintainer(&mut self, maintainer: &str) -> Result<DbResponse<Vec<String>>> {
+        let mut timer = PhaseTimer::start();
+        let mut result = self.owned_pkgs(MAINTAINERS_TABLE, maintainer)?;
+        result.sort();
+        timer.round_trips(self.take_requests());
+        Ok(timer.respond(result))
+    }
+
+    async fn get_pkgs_by_submitter(&mut self, submitter: &str) -> Result<DbResponse<Vec<String>>> {
+        let mut timer = PhaseTimer::start();
+        let mut result = self.owned_pkgs(SUBMITTERS_TABLE, submitter)?;
+        result.sort();
+        timer.round_trips(self.take_requests());
+        Ok(timer.respond(result))
+    }
+
+    async fn get_catalog_stats(&mut self) -> Result<DbResponse<CatalogStats>> {
+        let mut timer = PhaseTimer::start();
+        // Skytable has no aggregation, so every package is read and folded on the client
+        let basics = self.get_all_basic_package_data()?;
+        let additional = self.run_pipeline(ADDITIONAL_PKGS_TABLE, basics.iter().map(|basic| Query::new().arg("GET").arg(&basic.name)).collect())?;
+        let comments = self.run_pipeline(COMMENTS_TABLE, basics.iter().map(|basic| Query::new().arg("LGET").arg(&basic.name).arg("LEN")).collect())?;
+        timer.round_trips(self.take_requests());
+
+        let mut aggregates = Aggregates::default();
+        for ((basic, additional), comments) in basics.into_iter().zip(additional).zip(comments) {
+            let license = match additional {
+                Element::RespCode(RespCode::NotFound) => None,
+                element => AdditionalPackageData::from_element(element)?.license,
+            };
+            let comments = match comments {
+                Element::UnsignedInt(len) => len,
+                _ => 0,
+            };
+            aggregates.add_pkg(basic.votes, basic.popularity, license.as_deref(), &basic.maintainer, comments);
+        }
+        let result = aggregates.into_stats();
+        Ok(timer.respond(result))
+    }
+
+    async fn update_pkg_fields(&mut self, pkg_name: &str, patch: &BasicPackagePatch) -> Result<DbResponse<()>> {
+        patch.ensure_not_empty()?;
+        let mut timer = PhaseTimer::start();
+        self.request(|c| c.switch(BASIC_PKGS_TABLE))?;
+        let mut basic: BasicPackageData = self.request(|c| c.get(pkg_name))?;
+        let old = basic.clone();
+        patch.apply(&mut basic);
+        self.request(|c| c.update(pkg_name, &basic))?;
+        self.rerank(&old, &basic)?;
+        if basic.maintainer != old.maintainer {
+            self.unindex_owner(MAINTAINERS_TABLE, &old.maintainer, pkg_name)?;
+            self.request(|c| c.switch(MAINTAINERS_TABLE))?;
+            for query in owner_queries(&basic.maintainer, pkg_name) {
+                self.request(|c| c.run_query_raw(query))?;
+            }
+        }
+        timer.round_trips(self.take_requests());
+        Ok(timer.respond(()))
+    }
+
+    async fn increment_votes(&mut self, pkg_name: &str, delta: i32) -> Result<DbResponse<i32>> {
+        let mut timer = PhaseTimer::start();
+        self.request(|c| c.switch(BASIC_PKGS_TABLE))?;
+        let mut basic: BasicPackageData = self.request(|c| c.get(pkg_name))?;
+        let old = basic.clone();
+        basic.votes = match basic.votes.checked_add(delta) {
+            Some(votes) => votes,
+            None => bail!("Votes of package {} would overflow", pkg_name),
+        };
+        self.request(|c| c.update(pkg_name, &basic))?;
+        self.rerank(&old, &basic)?;
+        timer.round_trips(self.take_requests());
+        Ok(timer.respond(basic.votes))
+    }
+
+    async fn get_packages_occurences_in_deps(&mut self, pkg_names: &[String], groups: &[String]) -> Result<DbResponse<HashMap<String, u32>>> {
+        let mut result: HashMap<String, u32> = HashMap::new();
+
+        let mut timer = PhaseTimer::start();
+        self.ensure_reverse_dependencies()?;
+        // Served by the reverse dependency lists, which hold one entry per group and package listing the name
+        for pkg_name in pkg_names {
+            let listed: HashSet<(String, String)> = self.reverse_dependency_entries(pkg_name)?
+                .into_iter()
+                .filter(|entry| groups.is_empty() || groups.contains(&entry.group))
+                .map(|entry| (entry.group, entry.pkg))
+                .collect();
+            result.insert(pkg_name.to_owned(), listed.len() as u32);
+        }
+        timer.round_trips(self.take_requests());
+        Ok(timer.respond(result))
+    }
+
+    // Requests are written to a plain stream, after an I/O error it may hold part of a request or response
+    fn is_connection_error(&self, error: &anyhow::Error) -> bool {
+        error.chain().any(|cause| {
+            matches!(cause.downcast_ref::<skytable::error::Error>(), Some(skytable::error::Error::IoError(_))) || cause.is::<std::io::Error>()
+        })
+    }
 }
 
 //TODO ----------from scrapper -----------
@@ -222,7 +850,7 @@ impl FromSkyhashBytes for DbResponse<Vec<BasicPackageData>> {
             .map_err(|e| skytable::error::Error::ParseError(e.to_string()))?;
             pkgs.push(pkg);
         }
-        skytable::SkyResult::Ok(DbResponse { result: pkgs, duration: Duration::ZERO })
+        skytable::SkyResult::Ok(DbResponse::untimed(pkgs))
     }
 }
 
@@ -268,6 +896,39 @@ impl FromSkyhashBytes for PackageDependency {
     }
 }
 
+impl IntoSkyhashBytes for &ReverseDependency {
+    fn as_bytes(&self) -> Vec<u8> {
+        serde_json::to_vec(self).expect("Cannot serialize ReverseDependency to Vec<u8>")
+    }
+}
+
+impl IntoSkyhashBytes for &RankEntry {
+    fn as_bytes(&self) -> Vec<u8> {
+        serde_json::to_vec(self).expect("Cannot serialize RankEntry to Vec<u8>")
+    }
+}
+
+impl FromSkyhashBytes for RankEntry {
+    fn from_element(element: skytable::Element) -> SkyResult<Self> {
+        let bytes: Vec<u8> = element.try_element_into()?;
+        serde_json::from_slice(&bytes)
+            .map_err(|e| skytable::error::Error::ParseError(e.to_string()))
+    }
+}
+
+impl FromSkyhashBytes for ReverseDependencies {
+    fn from_element(element: skytable::Element) -> SkyResult<Self> {
+        let entries_bytes: Vec<Vec<u8>> = element.try_element_into()?;
+        let mut entries: Vec<ReverseDependency> = Vec::new();
+        for entry_bytes in entries_bytes {
+            let entry: ReverseDependency = serde_json::from_slice(&entry_bytes)
+                .map_err(|e| skytable::error::Error::ParseError(e.to_string()))?;
+            entries.push(entry);
+        }
+        skytable::SkyResult::Ok(ReverseDependencies { data: entries })
+    }
+}
+
 impl FromSkyhashBytes for Comments {
     fn from_element(element: skytable::Element) -> SkyResult<Self> {
         let mut comments: Vec<Comment> = Vec::new();
@@ -299,11 +960,13 @@ mod test {
     use super::SkytableClient;
     use anyhow::{Result, Ok};
     use super::DbActions;
+    use crate::{config::ConnectionConfig, commands::Db};
 
     #[tokio::test]
     async fn test_query() -> Result<()> {
-        let mut db = SkytableClient::try_new()?;
-        let result = db.get_packages_occurences_in_deps(&vec!["rust".to_string(), "go".to_string(), "sudo".to_string()]).await?;
+        let profile = ConnectionConfig::default().profile(&Db::Skytable, None)?;
+        let mut db = SkytableClient::try_new(&profile)?;
+        let result = db.get_packages_occurences_in_deps(&["rust".to_string(), "go".to_string(), "sudo".to_string()], &[]).await?;
         println!("{:?}", result);
         Ok(())
     }
@@ -0,0 +1,635 @@
+use std::{time::{Instant, Duration}, collections::HashMap};
+
+use anyhow::{Result, Ok, bail};
+use async_trait::async_trait;
+use rusqlite::{Connection, Transaction, params, types::ValueRef, OptionalExtension};
+use serde_json::{Map, Value};
+
+use crate::{models::{BasicPackageData, BasicPackagePatch, CatalogStats, DependencyTree, PackageData, AdditionalPackageData, Comment, PackageDependency}, config::ConnectionProfile};
+
+use super::{DbActions, DbResponse, Connect, PhaseTimer, dependency_graph, search, stats::{self, Aggregates}};
+
+const SCHEMA: &str = "
+    PRAGMA foreign_keys = ON;
+
+    CREATE TABLE IF NOT EXISTS pkgs (
+        name TEXT PRIMARY KEY,
+        version TEXT NOT NULL,
+        path_to_additional_data TEXT NOT NULL,
+        votes INTEGER NOT NULL,
+        popularity REAL NOT NULL,
+        description TEXT NOT NULL,
+        maintainer TEXT NOT NULL,
+        last_updated TEXT NOT NULL
+    );
+    CREATE INDEX IF NOT EXISTS pkgs_votes_idx ON pkgs(votes);
+    CREATE INDEX IF NOT EXISTS pkgs_popularity_idx ON pkgs(popularity);
+    CREATE INDEX IF NOT EXISTS pkgs_last_updated_idx ON pkgs(last_updated);
+    CREATE INDEX IF NOT EXISTS pkgs_maintainer_idx ON pkgs(maintainer);
+
+    CREATE TABLE IF NOT EXISTS pkgs_additional (
+        pkg_name TEXT PRIMARY KEY REFERENCES pkgs(name) ON DELETE CASCADE,
+        git_clone_url TEXT NOT NULL,
+        keywords TEXT,
+        license TEXT,
+        confilcts TEXT,
+        provides TEXT,
+        submitter TEXT NOT NULL,
+        first_submitted TEXT NOT NULL
+    );
+    CREATE INDEX IF NOT EXISTS pkgs_additional_submitter_idx ON pkgs_additional(submitter);
+
+    CREATE TABLE IF NOT EXISTS comments (
+        id INTEGER PRIMARY KEY,
+        pkg_name TEXT NOT NULL REFERENCES pkgs(name) ON DELETE CASCADE,
+        position INTEGER NOT NULL,
+        header TEXT NOT NULL,
+        content TEXT NOT NULL
+    );
+    CREATE INDEX IF NOT EXISTS comments_pkg_name_idx ON comments(pkg_name, position);
+
+    CREATE TABLE IF NOT EXISTS dependency_groups (
+        id INTEGER PRIMARY KEY,
+        pkg_name TEXT NOT NULL REFERENCES pkgs(name) ON DELETE CASCADE,
+        position INTEGER NOT NULL,
+        name TEXT NOT NULL
+    );
+    CREATE INDEX IF NOT EXISTS dependency_groups_pkg_name_idx ON dependency_groups(pkg_name, position);
+    CREATE INDEX IF NOT EXISTS dependency_groups_name_idx ON dependency_groups(name);
+
+    CREATE TABLE IF NOT EXISTS dependencies (
+        group_id INTEGER NOT NULL REFERENCES dependency_groups(id) ON DELETE CASCADE,
+        position INTEGER NOT NULL,
+        name TEXT NOT NULL
+    );
+    CREATE INDEX IF NOT EXISTS dependencies_group_id_idx ON dependencies(group_id, position);
+    CREATE INDEX IF NOT EXISTS dependencies_name_idx ON dependencies(name);
+
+    CREATE TABLE IF NOT EXISTS search_words (
+        pkg_name TEXT NOT NULL REFERENCES pkgs(name) ON DELETE CASCADE,
+        word TEXT NOT NULL,
+        PRIMARY KEY (word, pkg_name)
+    );
+    CREATE INDEX IF NOT EXISTS search_words_pkg_name_idx ON search_words(pkg_name);
+";
+
+const BASIC_COLUMNS: &str = "name, version, path_to_additional_data, votes, popularity, description, maintainer, last_updated";
+
+// The database runs in process, so executing a statement and reading its rows, decoding included,
+// is reported as one round-trip; transaction control statements are not counted
+pub struct SqliteDb {
+    connection: Connection
+}
+
+impl SqliteDb {
+    pub fn try_new(profile: &ConnectionProfile) -> Result<Self> {
+        let Some(path) = &profile.database else {
+            bail!("SQLite profile requires a database file path");
+        };
+        let connection = Connection::open(path)?;
+        connection.execute_batch(SCHEMA)?;
+        Ok(Self { connection })
+    }
+}
+
+fn sortable_column(field: &str) -> Result<&'static str> {
+    let column = match field {
+        "name" => "name",
+        "version" => "version",
+        "path_to_additional_data" => "path_to_additional_data",
+        "votes" => "votes",
+        "popularity" => "popularity",
+        "description" => "description",
+        "maintainer" => "maintainer",
+        "last_updated" => "last_updated",
+        _ => bail!("Unsuported field")
+    };
+    Ok(column)
+}
+
+fn basic_from_row(row: &rusqlite::Row) -> rusqlite::Result<BasicPackageData> {
+    rusqlite::Result::Ok(BasicPackageData {
+        name: row.get(0)?,
+        version: row.get(1)?,
+        path_to_additional_data: row.get(2)?,
+        votes: row.get(3)?,
+        popularity: row.get(4)?,
+        description: row.get(5)?,
+        maintainer: row.get(6)?,
+        last_updated: row.get(7)?,
+    })
+}
+
+fn value_from_ref(value: ValueRef) -> Value {
+    match value {
+        ValueRef::Null => Value::Null,
+        ValueRef::Integer(i) => Value::from(i),
+        ValueRef::Real(f) => Value::from(f),
+        ValueRef::Text(t) => Value::from(String::from_utf8_lossy(t).into_owned()),
+        ValueRef::Blob(b) => Value::from(b.to_vec()),
+    }
+}
+
+// Returns the number of statements executed
+fn insert_into(tx: &Transaction, pkg: &PackageData) -> Result<u32> {
+    // Removing the old row cascades to all data owned by the package
+    tx.execute("DELETE FROM pkgs WHERE name = ?1", params![pkg.basic.name])?;
+    tx.execute(
+        &format!("INSERT INTO pkgs ({}) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)", BASIC_COLUMNS),
+        params![
+            pkg.basic.name,
+            pkg.basic.version,
+            pkg.basic.path_to_additional_data,
+            pkg.basic.votes,
+            pkg.basic.popularity,
+            pkg.basic.description,
+            pkg.basic.maintainer,
+            pkg.basic.last_updated,
+        ],
+    )?;
+    tx.execute(
+        "INSERT INTO pkgs_additional (pkg_name, git_clone_url, keywords, license, confilcts, provides, submitter, first_submitted)
+            VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+        params![
+            pkg.basic.name,
+            pkg.additional.git_clone_url,
+            pkg.additional.keywords,
+            pkg.additional.license,
+            pkg.additional.confilcts,
+            pkg.additional.provides,
+            pkg.additional.submitter,
+            pkg.additional.first_submitted,
+        ],
+    )?;
+
+    for (idx, comment) in pkg.comments.iter().enumerate() {
+        tx.execute(
+            "INSERT INTO comments (pkg_name, position, header, content) VALUES (?1, ?2, ?3, ?4)",
+            params![pkg.basic.name, idx, comment.header, comment.content],
+        )?;
+    }
+
+    let words = index_words(tx, &pkg.basic.name, search::pkg_words(&pkg.basic.name, &pkg.basic.description, pkg.additional.keywords.as_deref()))?;
+
+    for (group_idx, dependency) in pkg.dependencies.iter().enumerate() {
+        tx.execute(
+            "INSERT INTO dependency_groups (pkg_name, position, name) VALUES (?1, ?2, ?3)",
+            params![pkg.basic.name, group_idx, dependency.group],
+        )?;
+        let group_id = tx.last_insert_rowid();
+        for (idx, dep) in dependency.packages.iter().enumerate() {
+            tx.execute(
+                "INSERT INTO dependencies (group_id, position, name) VALUES (?1, ?2, ?3)",
+                params![group_id, idx, dep],
+            )?;
+        }
+    }
+
+    let dependencies: usize = pkg.dependencies.iter().map(|dependency| 1 + dependency.packages.len()).sum();
+    Ok((3 + pkg.comments.len() + dependencies) as u32 + words)
+}
+
+// Returns the number of statements executed
+fn index_words(tx: &Transaction, pkg_name: &str, words: impl IntoIterator<Item = String>) -> Result<u32> {
+    tx.execute("DELETE FROM search_words WHERE pkg_name = ?1", params![pkg_name])?;
+    let mut stmt = tx.prepare_cached("INSERT INTO search_words (pkg_name, word) VALUES (?1, ?2)")?;
+    let mut statements = 1;
+    for word in words {
+        stmt.execute(params![pkg_name, word])?;
+        statements += 1;
+    }
+    Ok(statements)
+}
+
+#[async_trait]
+impl Connect for SqliteDb {
+    async fn connect(profile: &ConnectionProfile) -> Result<Self> {
+        Self::try_new(profile)
+    }
+}
+
+#[async_trait]
+impl DbActions for SqliteDb {
+    async fn get_custom_query_time(&mut self, query: &str) -> Result<Duration> {
+        let start = Instant::now();
+        let mut stmt = self.connection.prepare(query)?;
+        if stmt.column_count() == 0 {
+            stmt.execute([])?;
+        } else {
+            let mut rows = stmt.query([])?;
+            while rows.next()?.is_some() {}
+        }
+        Ok(start.elapsed())
+    }
+
+    async fn run_custom_query(&mut self, query: &str) -> Result<DbResponse<String>> {
+        let mut timer = PhaseTimer::start();
+        let mut stmt = self.connection.prepare(query)?;
+        if stmt.column_count() == 0 {
+            let changed = stmt.execute([])?;
+            timer.round_trip();
+            return Ok(timer.respond(format!("{} rows affected", changed)));
+        }
+
+        let columns: Vec<String> = stmt.column_names().into_iter().map(String::from).collect();
+        let mut rows = stmt.query([])?;
+        let mut records = Vec::new();
+        while let Some(row) = rows.next()? {
+            let mut record = Map::new();
+            for (idx, column) in columns.iter().enumerate() {
+                record.insert(column.clone(), value_from_ref(row.get_ref(idx)?));
+            }
+            records.push(Value::Object(record));
+        }
+        timer.round_trip();
+
+        let result = serde_json::to_string(&records)?;
+        Ok(timer.respond(result))
+    }
+
+    async fn sort_pkgs_by_field_with_limit(&mut self, field: &str, limit_start: u32, limit_end: u32) -> Result<DbResponse<Vec<String>>> {
+        let query = format!(
+            "SELECT name FROM pkgs ORDER BY {} DESC, name DESC LIMIT ?1 OFFSET ?2",
+            sortable_column(field)?
+        );
+
+        let mut timer = PhaseTimer::start();
+        let mut stmt = self.connection.prepare_cached(&query)?;
+        let result = stmt
+            .query_map(params![limit_end.saturating_sub(limit_start), limit_start], |row| row.get(0))?
+            .collect::<rusqlite::Result<Vec<String>>>()?;
+        timer.round_trip();
+        Ok(timer.respond(result))
+    }
+
+    async fn get_most_voted_pkgs(&mut self, number: u32) -> Result<DbResponse<Vec<BasicPackageData>>> {
+        let query = format!("SELECT {} FROM pkgs ORDER BY votes DESC, name DESC LIMIT ?1", BASIC_COLUMNS);
+
+        let mut timer = PhaseTimer::start();
+        let mut stmt = self.connection.prepare_cached(&query)?;
+        let result = stmt
+            .query_map(params![number], basic_from_row)?
+            .collect::<rusqlite::Result<Vec<BasicPackageData>>>()?;
+        timer.round_trip();
+        Ok(timer.respond(result))
+    }
+
+    async fn insert_pkg(&mut self, pkg: &PackageData) -> Result<DbResponse<()>> {
+        let mut timer = PhaseTimer::start();
+        let tx = self.connection.transaction()?;
+        let statements = insert_into(&tx, pkg)?;
+        tx.commit()?;
+        timer.round_trips(statements);
+        Ok(timer.respond(()))
+    }
+
+    async fn insert_pkgs(&mut self, pkgs: &[PackageData]) -> Result<DbResponse<()>> {
+        let mut timer = PhaseTimer::start();
+        let tx = self.connection.transaction()?;
+        let mut statements = 0;
+        for pkg in pkgs {
+            statements += insert_into(&tx, pkg)?;
+        }
+        tx.commit()?;
+        timer.round_trips(statements);
+        Ok(timer.respond(()))
+    }
+
+    async fn get_pkg(&mut self, name: &str) -> Result<DbResponse<PackageData>> {
+        let mut timer = PhaseTimer::start();
+        let basic = self.connection
+            .prepare_cached(&format!("SELECT {} FROM pkgs WHERE name = ?1", BASIC_COLUMNS))?
+            .query_row(params![name], basic_from_row)
+            .optional()?;
+        let Some(basic) = basic else {
+            bail!("Package {} not found", name);
+        };
+
+        let additional = self.connection
+            .prepare_cached("SELECT git_clone_url, keywords, license, confilcts, provides, submitter, first_submitted
+                FROM pkgs_additional WHERE pkg_name = ?1")?
+            .query_row(params![name], |row| rusqlite::Result::Ok(AdditionalPackageData {
+                git_clone_url: row.get(0)?,
+                keywords: row.get(1)?,
+                license: row.get(2)?,
+                confilcts: row.get(3)?,
+                provides: row.get(4)?,
+                submitter: row.get(5)?,
+                first_submitted: row.get(6)?,
+            }))?;
+
+        let comments = self.connection
+            .prepare_cached("SELECT header, content FROM comments WHERE pkg_name = ?1 ORDER BY position")?
+            .query_map(params![name], |row| rusqlite::Result::Ok(Comment { header: row.get(0)?, content: row.get(1)? }))?
+            .collect::<rusqlite::Result<Vec<Comment>>>()?;
+
+        let groups = self.connection
+            .prepare_cached("SELECT id, name FROM dependency_groups WHERE pkg_name = ?1 ORDER BY position")?
+            .query_map(params![name], |row| rusqlite::Result::Ok((row.get::<_, i64>(0)?, row.get::<_, String>(1)?)))?
+            .collect::<rusqlite::Result<Vec<(i64, String)>>>()?;
+
+        let statements = 4 + groups.len() as u32;
+        let mut dependencies = Vec::new();
+        for (group_id, group) in groups {
+            let packages = self.connection
+                .prepare_cached("SELECT name FROM dependencies WHERE group_id = ?1 ORDER BY position")?
+                .query_map(params![group_id], |row| row.get(0))?
+                .collect::<rusqlite::Result<Vec<String>>>()?;
+            dependencies.push(PackageDependency { group, packages });
+        }
+        timer.round_trips(statements);
+
+        let result = PackageData { basic, additional, comments, dependencies };
+        Ok(timer.respond(result))
+    }
+
+    async fn contains_pkg(&mut self, pkg_name: &str) -> Result<DbResponse<bool>> {
+        let mut timer = PhaseTimer::start();
+        let result = self.connection
+            .prepare_cached("SELECT EXISTS(SELECT 1 FROM pkgs WHERE name = ?1)")?
+            .query_row(params![pkg_name], |row| row.get(0))?;
+        timer.round_trip();
+        Ok(timer.respond(result))
+    }
+
+    async fn remove_comments(&mut self, pkg_name: &str) -> Result<DbResponse<()>> {
+        let mut timer = PhaseTimer::start();
+        self.connection.execute("DELETE FROM comments WHERE pkg_name = ?1", params![pkg_name])?;
+        timer.round_trip();
+        Ok(timer.respond(()))
+    }
+
+    async fn delete_pkg(&mut self, pkg_name: &str) -> Result<DbResponse<()>> {
+        let mut timer = PhaseTimer::start();
+        // Comments, dependencies and additional data are removed by the foreign key cascades
+        self.connection.execute("DELETE FROM pkgs WHERE name = ?1", params![pkg_name])?;
+        timer.round_trip();
+        Ok(timer.respond(()))
+    }
+
+    async fn get_reverse_dependencies(&mut self, pkg_name: &str, groups: &[String]) -> Result<DbResponse<Vec<String>>> {
+        // Served by the index on `dependencies.name`, which every insert keeps up to date
+        let mut query = "SELECT DISTINCT dependency_groups.pkg_name FROM dependencies
+            JOIN dependency_groups ON dependency_groups.id = dependencies.group_id
+            WHERE dependencies.name = ?1".to_owned();
+        if !groups.is_empty() {
+            let placeholders: Vec<String> = (0..groups.len()).map(|idx| format!("?{}", idx + 2)).collect();
+            query += &format!(" AND dependency_groups.name IN ({})", placeholders.join(", "));
+        }
+        query += " ORDER BY dependency_groups.pkg_name";
+
+        let mut timer = PhaseTimer::start();
+        let mut stmt = self.connection.prepare_cached(&query)?;
+        let params = std::iter::once(pkg_name).chain(groups.iter().map(String::as_str));
+        let result = stmt
+            .query_map(rusqlite::params_from_iter(params), |row| row.get(0))?
+            .collect::<rusqlite::Result<Vec<String>>>()?;
+        timer.round_trip();
+        Ok(timer.respond(result))
+    }
+
+    async fn resolve_dependency_tree(&mut self, pkg_name: &str, groups: &[String], max_depth: Option<u32>) -> Result<DbResponse<DependencyTree>> {
+        // The recursive CTE collects the whole closure, `UNION` stops it on cycles; depth is applied by the resolver
+        let group_filter = match groups.is_empty() {
+            true => String::new(),
+            false => {
+                let placeholders: Vec<String> = (0..groups.len()).map(|idx| format!("?{}", idx + 2)).collect();
+                format!(" AND dependency_groups.name IN ({})", placeholders.join(", "))
+            },
+        };
+        let query = format!("WITH RECURSIVE closure(name) AS (
+                SELECT ?1
+                UNION
+                SELECT dependencies.name FROM closure
+                JOIN dependency_groups ON dependency_groups.pkg_name = closure.name{filter}
+                JOIN dependencies ON dependencies.group_id = dependency_groups.id
+            )
+            SELECT pkgs.name, dependencies.name FROM closure
+            JOIN pkgs ON pkgs.name = closure.name
+            LEFT JOIN dependency_groups ON dependency_groups.pkg_name = pkgs.name{filter}
+            LEFT JOIN dependencies ON dependencies.group_id = dependency_groups.id", filter = group_filter);
+
+        let mut timer = PhaseTimer::start();
+        let mut stmt = self.connection.prepare_cached(&query)?;
+        let params = std::iter::once(pkg_name).chain(groups.iter().map(String::as_str));
+        let mut dependencies_of: HashMap<String, Vec<String>> = HashMap::new();
+        let mut rows = stmt.query(rusqlite::params_from_iter(params))?;
+        while let Some(row) = rows.next()? {
+            let deps = dependencies_of.entry(row.get(0)?).or_default();
+            if let Some(dep) = row.get::<_, Option<String>>(1)? {
+                deps.push(dep);
+            }
+        }
+        timer.round_trip();
+        let result = dependency_graph::resolve(pkg_name, max_depth, &dependencies_of)?;
+        Ok(timer.respond(result))
+    }
+
+    async fn search_pkgs(&mut self, query: &str, limit: u32) -> Result<DbResponse<Vec<String>>> {
+        let words = search::query_words(query)?;
+        // GLOB with a fixed prefix is served by the primary key of `search_words`, words never contain wildcards
+        let conditions: Vec<String> = (0..words.len())
+            .map(|idx| format!("name IN (SELECT pkg_name FROM search_words WHERE word GLOB ?{})", idx + 1))
+            .collect();
+        let query = format!("SELECT name FROM pkgs WHERE {} ORDER BY popularity DESC, name LIMIT ?{}", conditions.join(" AND "), words.len() + 1);
+
+        let mut timer = PhaseTimer::start();
+        let mut stmt = self.connection.prepare_cached(&query)?;
+        let patterns = words.iter().map(|word| rusqlite::types::Value::from(format!("{}*", word)));
+        let params = patterns.chain(std::iter::once(rusqlite::types::Value::from(limit as i64)));
+        let result = stmt
+            .query_map(rusqlite::params_from_iter(params), |row| row.get(0))?
+            .collect::<rusqlite::Result<Vec<String>>>()?;
+        timer.round_trip();
+        Ok(timer.respond(result))
+    }
+
+    async fn get_pkgs_by_maintainer(&mut self, maintainer: &str) -> Result<DbResponse<Vec<String>>> {
+        let mut timer = PhaseTimer::start();
+        let result = self.connection
+            .prepare_cached("SELECT name FROM pkgs WHERE maintainer = ?1 ORDER BY name")?
+            .query_map(params![maintainer], |row| row.get(0))?
+            .collect::<rusqlite::Result<Vec<String>>>()?;
+        timer.round_trip();
+        Ok(timer.respond(result))
+    }
+
+    async fn get_pkgs_by_submitter(&mut self, submitter: &str) -> Result<DbResponse<Vec<String>>> {
+        let mut timer = PhaseTimer::start();
+        let result = self.connection
+            .prepare_cached("SELECT pkg_name FROM pkgs_additional WHERE submitter = ?1 ORDER BY pkg_name")?
+            .query_map(params![submitter], |row| row.get(0))?
+            .collect::<rusqlite::Result<Vec<String>>>()?;
+        timer.round_trip();
+        Ok(timer.respond(result))
+    }
+
+    async fn get_catalog_stats(&mut self) -> Result<DbResponse<CatalogStats>> {
+        let bucket = stats::POPULARITY_BUCKETS.iter().enumerate().skip(1).rev()
+            .fold("CASE".to_owned(), |case, (idx, min)| format!("{} WHEN popularity >= {} THEN {}", case, min, idx)) + " ELSE 0 END";
+
+        let mut timer = PhaseTimer::start();
+        let mut aggregates = Aggregates::default();
+        (aggregates.pkg_count, aggregates.total_votes) = self.connection
+            .query_row("SELECT COUNT(*), COALESCE(SUM(votes), 0) FROM pkgs", [], |row| rusqlite::Result::Ok((row.get(0)?, row.get(1)?)))?;
+
+        let mut stmt = self.connection.prepare_cached(&format!("SELECT {} AS bucket, COUNT(*) FROM pkgs GROUP BY bucket", bucket))?;
+        let mut rows = stmt.query([])?;
+        while let Some(row) = rows.next()? {
+            aggregates.popularity_buckets[row.get::<_, usize>(0)?] = row.get(1)?;
+        }
+
+        for (query, counts) in [
+            ("SELECT license, COUNT(*) AS count FROM pkgs_additional WHERE license IS NOT NULL GROUP BY license ORDER BY count DESC, license LIMIT ?1", &mut aggregates.licenses),
+            ("SELECT maintainer, COUNT(*) AS count FROM pkgs GROUP BY maintainer ORDER BY count DESC, maintainer LIMIT ?1", &mut aggregates.maintainers),
+        ] {
+            let mut stmt = self.connection.prepare_cached(query)?;
+            let mut rows = stmt.query(params![stats::TOP_COUNT])?;
+            while let Some(row) = rows.next()? {
+                counts.insert(row.get(0)?, row.get(1)?);
+            }
+        }
+
+        let mut stmt = self.connection.prepare_cached("SELECT comments, COUNT(*) FROM (
+                SELECT COUNT(comments.id) AS comments FROM pkgs
+                LEFT JOIN comments ON comments.pkg_name = pkgs.name
+                GROUP BY pkgs.name
+            ) GROUP BY comments")?;
+        let mut rows = stmt.query([])?;
+        while let Some(row) = rows.next()? {
+            aggregates.comment_counts.insert(row.get(0)?, row.get(1)?);
+        }
+        timer.round_trips(5);
+
+        let result = aggregates.into_stats();
+        Ok(timer.respond(result))
+    }
+
+    async fn update_pkg_fields(&mut self, pkg_name: &str, patch: &BasicPackagePatch) -> Result<DbResponse<()>> {
+        patch.ensure_not_empty()?;
+        let mut timer = PhaseTimer::start();
+        let tx = self.connection.transaction()?;
+        let changed = tx
+            .prepare_cached("UPDATE pkgs SET
+                version = COALESCE(?2, version),
+                path_to_additional_data = COALESCE(?3, path_to_additional_data),
+                votes = COALESCE(?4, votes),
+                popularity = COALESCE(?5, popularity),
+                description = COALESCE(?6, description),
+                maintainer = COALESCE(?7, maintainer),
+                last_updated = COALESCE(?8, last_updated)
+                WHERE name = ?1")?
+            .execute(params![
+                pkg_name,
+                patch.version,
+                patch.path_to_additional_data,
+                patch.votes,
+                patch.popularity,
+                patch.description,
+                patch.maintainer,
+                patch.last_updated,
+            ])?;
+        if changed == 0 {
+            bail!("Package {} not found", pkg_name);
+        }
+        let mut statements = 1;
+        // The description is one of the sources of search words
+        if patch.description.is_some() {
+            let (description, keywords): (String, Option<String>) = tx.query_row(
+                "SELECT description, pkgs_additional.keywords FROM pkgs
+                    LEFT JOIN pkgs_additional ON pkgs_additional.pkg_name = pkgs.name
+                    WHERE name = ?1",
+                params![pkg_name],
+                |row| rusqlite::Result::Ok((row.get(0)?, row.get(1)?)),
+            )?;
+            statements += 1 + index_words(&tx, pkg_name, search::pkg_words(pkg_name, &description, keywords.as_deref()))?;
+        }
+        tx.commit()?;
+        timer.round_trips(statements);
+        Ok(timer.respond(()))
+    }
+
+    async fn increment_votes(&mut self, pkg_name: &str, delta: i32) -> Result<DbResponse<i32>> {
+        let mut timer = PhaseTimer::start();
+        let votes = self.connection
+            .prepare_cached("UPDATE pkgs SET votes = votes + ?2 WHERE name = ?1 AND votes + ?2 BETWEEN -2147483648 AND 2147483647 RETURNING votes")?
+            .query_row(params![pkg_name, delta], |row| row.get(0))
+            .optional()?;
+        timer.round_trip();
+        let Some(result) = votes else {
+            // Nothing changed, either because the package is missing or because the votes would leave the i32 range
+            let exists: bool = self.connection
+                .prepare_cached("SELECT EXISTS(SELECT 1 FROM pkgs WHERE name = ?1)")?
+                .query_row(params![pkg_name], |row| row.get(0))?;
+            timer.round_trip();
+            if exists {
+                bail!("Votes of package {} would overflow", pkg_name);
+            }
+            bail!("Package {} not found", pkg_name);
+        };
+        Ok(timer.respond(result))
+    }
+
+    async fn get_packages_occurences_in_deps(&mut self, pkg_names: &[String], groups: &[String]) -> Result<DbResponse<HashMap<String, u32>>> {
+        let mut result: HashMap<String, u32> = HashMap::new();
+        pkg_names.iter().for_each(|name| _ = result.insert(name.to_owned(), 0));
+        if pkg_names.is_empty() {
+            return Ok(DbResponse::untimed(result));
+        }
+
+        let placeholders = |offset: usize, count: usize| (offset..offset + count).map(|idx| format!("?{}", idx + 1)).collect::<Vec<_>>().join(", ");
+        // DISTINCT drops a name repeated within the same group of a package
+        let mut query = format!("SELECT name, COUNT(*) FROM (
+                SELECT DISTINCT dependencies.name, dependency_groups.pkg_name, dependency_groups.name AS group_name FROM dependencies
+                JOIN dependency_groups ON dependency_groups.id = dependencies.group_id
+                WHERE dependencies.name IN ({})", placeholders(0, pkg_names.len()));
+        if !groups.is_empty() {
+            query += &format!(" AND dependency_groups.name IN ({})", placeholders(pkg_names.len(), groups.len()));
+        }
+        query += ") GROUP BY name";
+
+        let mut timer = PhaseTimer::start();
+        let mut stmt = self.connection.prepare_cached(&query)?;
+        let mut rows = stmt.query(rusqlite::params_from_iter(pkg_names.iter().chain(groups)))?;
+        while let Some(row) = rows.next()? {
+            result.insert(row.get(0)?, row.get(1)?);
+        }
+        timer.round_trip();
+        Ok(timer.respond(result))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::SqliteDb;
+    use anyhow::{Result, Ok};
+    use super::DbActions;
+    use crate::{config::ConnectionConfig, commands::Db};
+
+    #[tokio::test]
+    async fn test_query() -> Result<()> {
+        let mut profile = ConnectionConfig::default().profile(&Db::Sqlite, None)?;
+        profile.database = Some(":memory:".into());
+        let mut db = SqliteDb::try_new(&profile)?;
+
+        let pkg = serde_json::from_str(include_str!("../../../pkg.txt"))?;
+        db.insert_pkg(&pkg).await?;
+
+        let result = db.get_pkg("test-7777").await?;
+        assert_eq!(result.result.comments.len(), 2);
+        assert_eq!(result.result.dependencies[1].packages, vec!["gawk-git", "gawk-fake"]);
+
+        let names = vec!["gawk-git".to_string(), "go".to_string()];
+        let result = db.get_packages_occurences_in_deps(&names, &[]).await?;
+        assert_eq!(result.result["gawk-git"], 1);
+        assert_eq!(result.result["go"], 0);
+        let result = db.get_packages_occurences_in_deps(&names, &["fuse2".to_string()]).await?;
+        assert_eq!(result.result["gawk-git"], 0);
+
+        db.delete_pkg("test-7777").await?;
+        assert!(db.get_pkg("test-7777").await.is_err());
+        let orphans: u32 = db.connection.query_row("SELECT COUNT(*) FROM dependencies", [], |row| row.get(0))?;
+        assert_eq!(orphans, 0);
+        Ok(())
+    }
+}
@@ -0,0 +1,63 @@
+use std::collections::{BTreeMap, HashMap};
+
+use crate::models::{CatalogStats, CommentCount, HistogramBucket, NamedCount};
+
+/// Lower bounds of the popularity histogram buckets, exact in both `f32` and `f64` so every backend splits alike.
+pub const POPULARITY_BUCKETS: [f32; 5] = [0.0, 0.25, 1.0, 4.0, 16.0];
+/// Length of the license and maintainer rankings.
+pub const TOP_COUNT: usize = 10;
+
+/// Index of the histogram bucket holding the popularity, values below zero fall into the first one.
+pub fn popularity_bucket(popularity: f32) -> usize {
+    POPULARITY_BUCKETS.iter().rposition(|min| popularity >= *min).unwrap_or(0)
+}
+
+/// Partial results from which `CatalogStats` are built, filled either package by package or from native aggregates.
+#[derive(Default)]
+pub struct Aggregates {
+    pub pkg_count: u64,
+    pub total_votes: i64,
+    pub popularity_buckets: [u64; POPULARITY_BUCKETS.len()],
+    pub licenses: HashMap<String, u64>,
+    pub maintainers: HashMap<String, u64>,
+    pub comment_counts: BTreeMap<u64, u64>,
+}
+
+impl Aggregates {
+    pub fn add_pkg(&mut self, votes: i32, popularity: f32, license: Option<&str>, maintainer: &str, comments: u64) {
+        self.pkg_count += 1;
+        self.total_votes += votes as i64;
+        self.popularity_buckets[popularity_bucket(popularity)] += 1;
+        if let Some(license) = license {
+            *self.licenses.entry(license.to_owned()).or_default() += 1;
+        }
+        *self.maintainers.entry(maintainer.to_owned()).or_default() += 1;
+        *self.comment_counts.entry(comments).or_default() += 1;
+    }
+
+    pub fn into_stats(self) -> CatalogStats {
+        let average_votes = match self.pkg_count {
+            0 => 0.0,
+            count => self.total_votes as f64 / count as f64,
+        };
+        CatalogStats {
+            pkg_count: self.pkg_count,
+            total_votes: self.total_votes,
+            average_votes,
+            popularity_histogram: POPULARITY_BUCKETS.iter()
+                .zip(self.popularity_buckets)
+                .map(|(min, count)| HistogramBucket { min: *min, count })
+                .collect(),
+            top_licenses: top(self.licenses),
+            top_maintainers: top(self.maintainers),
+            comment_counts: self.comment_counts.into_iter().map(|(comments, pkgs)| CommentCount { comments, pkgs }).collect(),
+        }
+    }
+}
+
+fn top(counts: HashMap<String, u64>) -> Vec<NamedCount> {
+    let mut counts: Vec<NamedCount> = counts.into_iter().map(|(name, count)| NamedCount { name, count }).collect();
+    counts.sort_by(|a, b| b.count.cmp(&a.count).then_with(|| a.name.cmp(&b.name)));
+    counts.truncate(TOP_COUNT);
+    counts
+}
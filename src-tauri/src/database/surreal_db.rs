@@ -1,43 +1,227 @@
-use std::{time::{Instant, Duration}, collections::HashMap};
+use std::{time::{Instant, Duration}, collections::{HashMap, HashSet}};
 
 
-use crate::models::{PackageData, BasicPackageData, PackageDependency};
+use crate::{models::{PackageData, BasicPackageData, BasicPackagePatch, CatalogStats, DependencyTree, NamedCount, PackageDependency}, config::ConnectionProfile};
 
-use super::{DbActions, DbResponse};
-use anyhow::{Result, Ok};
+use super::{DbActions, DbResponse, Connect, PhaseTimer, dependency_graph, search, stats::{self, Aggregates}};
+use anyhow::{Result, Ok, bail};
 use async_trait::async_trait;
-use surrealdb::{Surreal, engine::remote::ws::{Ws, Client}, opt::auth::Root, Response};
-
-type SurResult<T> = Result<T, surrealdb::Error>;
+use serde::{Deserialize, Serialize};
+use surrealdb::{Surreal, engine::remote::ws::{Ws, Wss, Client}, error::Api, opt::auth::Root, sql::Thing, Response};
 
+// Every query, a batch included, is one round-trip. The client does not expose the execution times the server reports,
+// and `take` converting the results of a query is the deserialization
 pub struct SurrealDbClient {
     db: Surreal<Client>,
 }
 
 impl SurrealDbClient {
-    pub async fn try_new() -> Result<Self> {
-        let db = Surreal::new::<Ws>("127.0.0.1:8000").await?;
+    pub async fn try_new(profile: &ConnectionProfile) -> Result<Self> {
+        let address = format!("{}:{}", profile.host, profile.port);
+        let db = if profile.tls {
+            Surreal::new::<Wss>(address).await?
+        } else {
+            Surreal::new::<Ws>(address).await?
+        };
 
-        db.signin(Root {
-            username: "root",
-            password: "root",
-        })
-        .await?;
+        if let (Some(username), Some(password)) = (&profile.username, &profile.password) {
+            db.signin(Root {
+                username,
+                password,
+            })
+            .await?;
+        }
+
+        let namespace = profile.namespace.as_deref().unwrap_or("aur");
+        let database = profile.database.as_deref().unwrap_or("packages");
+        db.use_ns(namespace).use_db(database).await?;
+
+        // Reverse dependency lookups filter edges by their target; redefining an index rebuilds it, so only do it once
+        let info: Option<serde_json::Value> = db.query("INFO FOR TABLE depends_on").await?.take(0)?;
+        if info.as_ref().and_then(|info| info.get("ix")).and_then(|ix| ix.get("depends_on_out_idx")).is_none() {
+            db.query("DEFINE INDEX depends_on_out_idx ON TABLE depends_on COLUMNS out").await?;
+        }
+
+        // Words are split like `search::tokenize` does and indexed by their prefixes, as searches match word prefixes
+        let info: Option<serde_json::Value> = db.query("INFO FOR TABLE pkgs").await?.take(0)?;
+        let indexes = info.as_ref().and_then(|info| info.get("ix"));
+        if indexes.and_then(|ix| ix.get("pkgs_maintainer_idx")).is_none() {
+            db.query("DEFINE INDEX pkgs_maintainer_idx ON TABLE pkgs COLUMNS basic.maintainer")
+                .query("DEFINE INDEX pkgs_submitter_idx ON TABLE pkgs COLUMNS additional.submitter")
+                .await?;
+        }
+        if indexes.and_then(|ix| ix.get("pkgs_description_search_idx")).is_none() {
+            db.query("DEFINE ANALYZER pkg_words TOKENIZERS blank, punct FILTERS lowercase, edgengram(1, 32)")
+                .query("DEFINE INDEX pkgs_name_search_idx ON TABLE pkgs COLUMNS basic.name SEARCH ANALYZER pkg_words BM25")
+                .query("DEFINE INDEX pkgs_keywords_search_idx ON TABLE pkgs COLUMNS additional.keywords SEARCH ANALYZER pkg_words BM25")
+                .query("DEFINE INDEX pkgs_description_search_idx ON TABLE pkgs COLUMNS basic.description SEARCH ANALYZER pkg_words BM25")
+                .await?;
+        }
 
-        db.use_ns("aur").use_db("packages").await?;
+        let client = Self { db };
+        client.ensure_dependency_edges().await?;
+        Ok(client)
+    }
 
-        Ok(Self { db })
+    // Packages written by other clients, such as the scraper, only have their dependency lists; the `depends_on` edges
+    // of the ones not marked with `edges_indexed` are written when connecting. Finding them scans the table, so it is
+    // not repeated before lookups, packages written through this client get their edges on insert
+    async fn ensure_dependency_edges(&self) -> Result<()> {
+        let mut response = self.db.query("SELECT basic.name AS name, dependencies FROM pkgs WHERE edges_indexed != true").await?;
+        let unindexed: Vec<UnindexedPkg> = response.take(0)?;
+        for chunk in unindexed.chunks(EDGES_BATCH_SIZE) {
+            let mut batch = Batch::default();
+            for pkg in chunk {
+                batch.set_dependency_edges(&pkg.name, &pkg.dependencies)?;
+            }
+            batch.execute(&self.db).await?;
+        }
+        Ok(())
     }
 }
 
-fn skip_already_exist_error<T>(res: SurResult<T>) -> Result<()> {
-    if let Err(e) = res {
-        if !e.to_string().contains("already exists") {
-            return Err(e.into());
+// Statements sent in a single transaction, with the parameters they refer to
+#[derive(Default)]
+struct Batch {
+    statements: Vec<String>,
+    records: Vec<(String, Thing)>,
+    values: Vec<(String, serde_json::Value)>,
+}
+
+impl Batch {
+    fn next_param(&self) -> String {
+        format!("p{}", self.records.len() + self.values.len())
+    }
+
+    // Each returns the name under which the parameter is used in statements
+    fn record(&mut self, table: &str, id: &str) -> String {
+        let name = self.next_param();
+        self.records.push((name.clone(), Thing::from((table, id))));
+        format!("${}", name)
+    }
+
+    fn value(&mut self, value: impl Serialize) -> Result<String> {
+        let name = self.next_param();
+        self.values.push((name.clone(), serde_json::to_value(value)?));
+        Ok(format!("${}", name))
+    }
+
+    // UPDATE creates missing records and replaces the content of existing ones, so an already stored package is
+    // replaced rather than aborting the whole transaction
+    fn set_pkg(&mut self, pkg: &PackageData) -> Result<()> {
+        let record = self.record("pkgs", &pkg.basic.name);
+        let content = self.value(pkg)?;
+        self.statements.push(format!("UPDATE {} CONTENT {}", record, content));
+        self.set_dependency_edges(&pkg.basic.name, &pkg.dependencies)
+    }
+
+    // Replaces the `depends_on` edges going out of the package, `kind` holds the dependency group
+    // and `name` the name of the dependency, which may not be in the database. The package is marked
+    // with `edges_indexed`, so the edges are not written again by `ensure_dependency_edges`
+    fn set_dependency_edges(&mut self, pkg_name: &str, dependencies: &[PackageDependency]) -> Result<()> {
+        let from = self.record("pkgs", pkg_name);
+        self.statements.push(format!("DELETE depends_on WHERE in = {}", from));
+        self.statements.push(format!("UPDATE {} SET edges_indexed = true", from));
+        for dependency in dependencies {
+            let kind = self.value(&dependency.group)?;
+            for dep in &dependency.packages {
+                let to = self.record("pkgs", dep);
+                let name = self.value(dep)?;
+                self.statements.push(format!("RELATE {}->depends_on->{} SET kind = {}, name = {}", from, to, kind, name));
+            }
+        }
+        Ok(())
+    }
+
+    async fn execute(self, db: &Surreal<Client>) -> Result<()> {
+        let count = self.statements.len();
+        if count == 0 {
+            return Ok(());
         }
+        let mut query = db.query(format!("BEGIN TRANSACTION; {}; COMMIT TRANSACTION;", self.statements.join("; ")));
+        for binding in self.records {
+            query = query.bind(binding);
+        }
+        for binding in self.values {
+            query = query.bind(binding);
+        }
+        let mut response = query.await?;
+
+        // Statement errors, including a cancelled transaction, are only reported per result
+        for idx in 0..count {
+            let _: Vec<serde_json::Value> = response.take(idx)?;
+        }
+        Ok(())
     }
+}
+
+#[derive(Deserialize)]
+struct Totals {
+    count: u64,
+    votes: i64,
+}
+
+#[derive(Deserialize)]
+struct GroupCount {
+    count: u64,
+}
+
+#[derive(Deserialize)]
+struct CommentsCount {
+    comments: u64,
+    count: u64,
+}
+
+#[derive(Deserialize)]
+struct SearchCandidate {
+    name: String,
+    description: String,
+    keywords: Option<String>,
+    popularity: f32,
+}
 
-    Ok(())
+#[derive(Deserialize)]
+struct DependencyEdge {
+    name: String,
+}
+
+#[derive(Deserialize)]
+struct DependencyEdges {
+    name: String,
+    deps: Vec<String>,
+}
+
+#[derive(Deserialize)]
+struct UnindexedPkg {
+    name: String,
+    #[serde(default)]
+    dependencies: Vec<PackageDependency>,
+}
+
+// Packages whose edges are written by one transaction of `ensure_dependency_edges`
+const EDGES_BATCH_SIZE: usize = 500;
+
+// Fields are interpolated into the sorting query, so only the ones of `BasicPackageData` are accepted
+fn sortable_field(field: &str) -> Result<&'static str> {
+    let field = match field {
+        "name" => "name",
+        "version" => "version",
+        "path_to_additional_data" => "path_to_additional_data",
+        "votes" => "votes",
+        "popularity" => "popularity",
+        "description" => "description",
+        "maintainer" => "maintainer",
+        "last_updated" => "last_updated",
+        _ => bail!("Unsuported field")
+    };
+    Ok(field)
+}
+
+#[async_trait]
+impl Connect for SurrealDbClient {
+    async fn connect(profile: &ConnectionProfile) -> Result<Self> {
+        Self::try_new(profile).await
+    }
 }
 
 #[async_trait]
@@ -49,82 +233,341 @@ impl DbActions for SurrealDbClient {
     }
 
     async fn run_custom_query(&mut self, query: &str) -> Result<DbResponse<String>> {
-        let start = Instant::now();
+        let mut timer = PhaseTimer::start();
         let mut response: Response = self.db.query(query).await?;
-        let duration = start.elapsed();
+        timer.round_trip();
 
         let result: Option<PackageData> = response.take(0)?;
+        timer.deserialization();
         if let Some(data) = result {
             let result = serde_json::to_string(&data)?;
-            return Ok(DbResponse { result, duration });
+            return Ok(timer.respond(result));
         }
-        Ok(DbResponse { result: "No data found".to_owned(), duration })
+        Ok(timer.respond("No data found".to_owned()))
     }
 
     async fn sort_pkgs_by_field_with_limit(&mut self, field: &str, limit_start: u32, limit_end: u32) -> Result<DbResponse<Vec<String>>> {
         let query = 
             format!("SELECT VALUE name FROM (SELECT basic.name as name, basic.{} as key 
-                FROM pkgs ORDER BY key DESC LIMIT BY {} START AT {})",
-                field,
-                limit_end.to_string(),
-                limit_start.to_string()
+                FROM pkgs ORDER BY key DESC, name DESC LIMIT BY {} START AT {})",
+                sortable_field(field)?,
+                limit_end.saturating_sub(limit_start),
+                limit_start
             );
 
-        let start = Instant::now();
-        let result: Vec<String> = self.db.query(query).await?.take(0)?;
-        let duration = start.elapsed();
-        Ok(DbResponse { result, duration })
+        let mut timer = PhaseTimer::start();
+        let mut response = self.db.query(query).await?;
+        timer.round_trip();
+        let result: Vec<String> = response.take(0)?;
+        timer.deserialization();
+        Ok(timer.respond(result))
     }
 
     async fn get_most_voted_pkgs(&mut self, number: u32) -> Result<DbResponse<Vec<BasicPackageData>>> {
         let query = format!("SELECT VALUE basic from 
-            (SELECT basic, basic.votes as votes from pkgs ORDER BY votes DESC LIMIT BY {})", number.to_string());
+            (SELECT basic, basic.votes as votes, basic.name as name from pkgs ORDER BY votes DESC, name DESC LIMIT BY {})", number.to_string());
         
-        let start = Instant::now();
-        let result: Vec<BasicPackageData> = self.db.query(query).await?.take(0)?;
-        let duration = start.elapsed();
-        Ok(DbResponse { result, duration })
+        let mut timer = PhaseTimer::start();
+        let mut response = self.db.query(query).await?;
+        timer.round_trip();
+        let result: Vec<BasicPackageData> = response.take(0)?;
+        timer.deserialization();
+        Ok(timer.respond(result))
     }
 
     async fn insert_pkg(&mut self, pkg: &PackageData) -> Result<DbResponse<()>> {
-        let start = Instant::now();
-        let _res: SurResult<()> = self
-            .db
-            .create(("pkgs", &pkg.basic.name))
-            .content(&pkg)
-            .await;
-        let duration = start.elapsed();
-        Ok(DbResponse { result: (), duration })
+        let mut batch = Batch::default();
+        batch.set_pkg(pkg)?;
+
+        let mut timer = PhaseTimer::start();
+        batch.execute(&self.db).await?;
+        timer.round_trip();
+        Ok(timer.respond(()))
+    }
+
+    async fn insert_pkgs(&mut self, pkgs: &[PackageData]) -> Result<DbResponse<()>> {
+        let mut batch = Batch::default();
+        for pkg in pkgs {
+            batch.set_pkg(pkg)?;
+        }
+
+        let mut timer = PhaseTimer::start();
+        batch.execute(&self.db).await?;
+        timer.round_trip();
+        Ok(timer.respond(()))
     }
 
     async fn get_pkg(&mut self, name: &str) -> Result<DbResponse<PackageData>> {
-        let start = Instant::now();
+        let mut timer = PhaseTimer::start();
         let result: PackageData = self.db.select(("pkgs", name)).await?;
-        let duration = start.elapsed();
-        Ok(DbResponse { result , duration })
+        timer.round_trip();
+        Ok(timer.respond(result))
+    }
+
+    async fn contains_pkg(&mut self, pkg_name: &str) -> Result<DbResponse<bool>> {
+        let mut timer = PhaseTimer::start();
+        let mut response = self.db.query("SELECT VALUE id FROM type::thing('pkgs', $name)").bind(("name", pkg_name)).await?;
+        timer.round_trip();
+        let ids: Vec<Thing> = response.take(0)?;
+        timer.deserialization();
+        Ok(timer.respond(!ids.is_empty()))
     }
 
     async fn remove_comments(&mut self, pkg_name: &str) -> Result<DbResponse<()>> {
         let query = format!("UPDATE pkgs SET comments = [] WHERE basic.name = '{}'", pkg_name);
-        let duration = self.get_custom_query_time(&query).await?;
-        Ok(DbResponse { result: (), duration })
+        let mut timer = PhaseTimer::start();
+        self.db.query(query).await?;
+        timer.round_trip();
+        Ok(timer.respond(()))
+    }
+
+    async fn delete_pkg(&mut self, pkg_name: &str) -> Result<DbResponse<()>> {
+        // Edges of packages depending on this one are kept, they still list it
+        let mut batch = Batch::default();
+        let record = batch.record("pkgs", pkg_name);
+        batch.statements.push(format!("DELETE depends_on WHERE in = {}", record));
+        batch.statements.push(format!("DELETE {}", record));
+
+        let mut timer = PhaseTimer::start();
+        batch.execute(&self.db).await?;
+        timer.round_trip();
+        Ok(timer.respond(()))
+    }
+
+    async fn get_reverse_dependencies(&mut self, pkg_name: &str, groups: &[String]) -> Result<DbResponse<Vec<String>>> {
+        let mut query = "SELECT VALUE in.basic.name FROM depends_on WHERE out = type::thing('pkgs', $name)".to_owned();
+        if !groups.is_empty() {
+            query += " AND kind INSIDE $groups";
+        }
+
+        let mut timer = PhaseTimer::start();
+        let mut response = self.db.query(query)
+            .bind(("name", pkg_name))
+            .bind(("groups", groups))
+            .await?;
+        timer.round_trip();
+        let mut result: Vec<String> = response.take(0)?;
+        timer.deserialization();
+        result.sort();
+        result.dedup();
+        Ok(timer.respond(result))
+    }
+
+    async fn resolve_dependency_tree(&mut self, pkg_name: &str, groups: &[String], max_depth: Option<u32>) -> Result<DbResponse<DependencyTree>> {
+        // One graph traversal per level, packages without a record do not come back and end up missing
+        let query = match groups.is_empty() {
+            true => "SELECT basic.name AS name, ->depends_on.name AS deps FROM $frontier",
+            false => "SELECT basic.name AS name, ->(depends_on WHERE kind INSIDE $groups).name AS deps FROM $frontier",
+        };
+
+        let mut timer = PhaseTimer::start();
+        let mut dependencies_of: HashMap<String, Vec<String>> = HashMap::new();
+        let mut visited = HashSet::from([pkg_name.to_owned()]);
+        let mut frontier = vec![pkg_name.to_owned()];
+        let mut depth = 0;
+        // Packages at `max_depth` are fetched too, only to tell them apart from missing ones
+        while !frontier.is_empty() && depth <= max_depth.unwrap_or(u32::MAX) {
+            let records: Vec<Thing> = frontier.iter().map(|name| Thing::from(("pkgs", name.as_str()))).collect();
+            timer.processing();
+            let mut response = self.db.query(query)
+                .bind(("frontier", records))
+                .bind(("groups", groups))
+                .await?;
+            timer.round_trip();
+            let level: Vec<DependencyEdges> = response.take(0)?;
+            timer.deserialization();
+
+            frontier = level.iter()
+                .flat_map(|edges| &edges.deps)
+                .filter(|dep| visited.insert(dep.to_string()))
+                .cloned()
+                .collect();
+            dependencies_of.extend(level.into_iter().map(|edges| (edges.name, edges.deps)));
+            depth += 1;
+        }
+        let result = dependency_graph::resolve(pkg_name, max_depth, &dependencies_of)?;
+        Ok(timer.respond(result))
+    }
+
+    async fn search_pkgs(&mut self, query: &str, limit: u32) -> Result<DbResponse<Vec<String>>> {
+        let words = search::query_words(query)?;
+        // Every word gets its own condition over the three indexed fields and all of them have to hold, so a package
+        // is only a candidate when each word is found in one of its fields; the exact rule is applied to the candidates
+        let conditions: Vec<String> = (0..words.len())
+            .map(|idx| format!("(basic.name @@ $w{0} OR basic.description @@ $w{0} OR additional.keywords @@ $w{0})", idx))
+            .collect();
+        let mut query = self.db.query(format!(
+            "SELECT basic.name AS name, basic.description AS description, additional.keywords AS keywords, basic.popularity AS popularity
+                FROM pkgs WHERE {}",
+            conditions.join(" AND ")
+        ));
+        for (idx, word) in words.iter().enumerate() {
+            query = query.bind((format!("w{}", idx), word));
+        }
+
+        let mut timer = PhaseTimer::start();
+        let mut response = query.await?;
+        timer.round_trip();
+        let candidates: Vec<SearchCandidate> = response.take(0)?;
+        timer.deserialization();
+        let hits = candidates.into_iter()
+            .filter(|pkg| search::matches(&search::pkg_words(&pkg.name, &pkg.description, pkg.keywords.as_deref()), &words))
+            .map(|pkg| (pkg.name, pkg.popularity))
+            .collect();
+        let result = search::rank(hits, limit);
+        Ok(timer.respond(result))
+    }
+
+    async fn get_pkgs_by_maintainer(&mut self, maintainer: &str) -> Result<DbResponse<Vec<String>>> {
+        let mut timer = PhaseTimer::start();
+        let mut response = self.db.query("SELECT VALUE basic.name FROM pkgs WHERE basic.maintainer = $maintainer")
+            .bind(("maintainer", maintainer))
+            .await?;
+        timer.round_trip();
+        let mut result: Vec<String> = response.take(0)?;
+        timer.deserialization();
+        result.sort();
+        Ok(timer.respond(result))
     }
 
-    async fn get_packages_occurences_in_deps(&mut self, pkg_names: &Vec<String>) -> Result<DbResponse<HashMap<String, u32>>> {
+    async fn get_pkgs_by_submitter(&mut self, submitter: &str) -> Result<DbResponse<Vec<String>>> {
+        let mut timer = PhaseTimer::start();
+        let mut response = self.db.query("SELECT VALUE basic.name FROM pkgs WHERE additional.submitter = $submitter")
+            .bind(("submitter", submitter))
+            .await?;
+        timer.round_trip();
+        let mut result: Vec<String> = response.take(0)?;
+        timer.deserialization();
+        result.sort();
+        Ok(timer.respond(result))
+    }
+
+    async fn get_catalog_stats(&mut self) -> Result<DbResponse<CatalogStats>> {
+        let mut statements = vec![
+            "SELECT count() AS count, math::sum(basic.votes) AS votes FROM pkgs GROUP ALL".to_owned(),
+            "SELECT additional.license AS name, count() AS count FROM pkgs WHERE additional.license != NONE AND additional.license != NULL GROUP BY name".to_owned(),
+            "SELECT basic.maintainer AS name, count() AS count FROM pkgs GROUP BY name".to_owned(),
+            "SELECT array::len(comments) AS comments, count() AS count FROM pkgs GROUP BY comments".to_owned(),
+        ];
+        let buckets = stats::POPULARITY_BUCKETS;
+        for idx in 0..buckets.len() {
+            // The first bucket also takes anything below its bound, the last one has no upper bound
+            let mut conditions = vec![];
+            if idx > 0 {
+                conditions.push(format!("basic.popularity >= {}", buckets[idx]));
+            }
+            if let Some(next) = buckets.get(idx + 1) {
+                conditions.push(format!("basic.popularity < {}", next));
+            }
+            statements.push(format!("SELECT count() AS count FROM pkgs WHERE {} GROUP ALL", conditions.join(" AND ")));
+        }
+
+        let mut timer = PhaseTimer::start();
+        let mut response = self.db.query(statements.join("; ")).await?;
+        timer.round_trip();
+        let mut aggregates = Aggregates::default();
+        let totals: Vec<Totals> = response.take(0)?;
+        if let Some(totals) = totals.first() {
+            aggregates.pkg_count = totals.count;
+            aggregates.total_votes = totals.votes;
+        }
+        let licenses: Vec<NamedCount> = response.take(1)?;
+        aggregates.licenses = licenses.into_iter().map(|license| (license.name, license.count)).collect();
+        let maintainers: Vec<NamedCount> = response.take(2)?;
+        aggregates.maintainers = maintainers.into_iter().map(|maintainer| (maintainer.name, maintainer.count)).collect();
+        let comments: Vec<CommentsCount> = response.take(3)?;
+        aggregates.comment_counts = comments.into_iter().map(|row| (row.comments, row.count)).collect();
+        for idx in 0..buckets.len() {
+            let bucket: Vec<GroupCount> = response.take(4 + idx)?;
+            aggregates.popularity_buckets[idx] = bucket.first().map_or(0, |bucket| bucket.count);
+        }
+        let result = aggregates.into_stats();
+        Ok(timer.respond(result))
+    }
+
+    async fn update_pkg_fields(&mut self, pkg_name: &str, patch: &BasicPackagePatch) -> Result<DbResponse<()>> {
+        patch.ensure_not_empty()?;
+        // Unset fields are skipped when serializing, so only the changed ones are assigned
+        let serde_json::Value::Object(fields) = serde_json::to_value(patch)? else {
+            bail!("Patch must serialize to an object");
+        };
+        let assignments: Vec<String> = fields.keys().map(|field| format!("basic.{0} = $patch.{0}", field)).collect();
+        let query = format!("UPDATE type::thing('pkgs', $name) SET {} WHERE basic.name = $name", assignments.join(", "));
+
+        let mut timer = PhaseTimer::start();
+        let mut response = self.db.query(query).bind(("name", pkg_name)).bind(("patch", patch)).await?;
+        timer.round_trip();
+
+        let updated: Vec<serde_json::Value> = response.take(0)?;
+        timer.deserialization();
+        if updated.is_empty() {
+            bail!("Package {} not found", pkg_name);
+        }
+        Ok(timer.respond(()))
+    }
+
+    async fn increment_votes(&mut self, pkg_name: &str, delta: i32) -> Result<DbResponse<i32>> {
+        // Votes are stored as 64-bit integers, the range check keeps them readable as i32. The second statement tells
+        // a missing package apart from a rejected delta when nothing was updated
+        let query = "
+            UPDATE type::thing('pkgs', $name) SET basic.votes += $delta
+                WHERE basic.name = $name AND basic.votes + $delta >= $min AND basic.votes + $delta <= $max;
+            SELECT VALUE id FROM type::thing('pkgs', $name);
+        ";
+
+        let mut timer = PhaseTimer::start();
+        let mut response = self.db.query(query)
+            .bind(("name", pkg_name))
+            .bind(("delta", delta))
+            .bind(("min", i32::MIN))
+            .bind(("max", i32::MAX))
+            .await?;
+        timer.round_trip();
+
+        let updated: Vec<PackageData> = response.take(0)?;
+        let ids: Vec<Thing> = response.take(1)?;
+        timer.deserialization();
+        let Some(pkg) = updated.into_iter().next() else {
+            if !ids.is_empty() {
+                bail!("Votes of package {} would overflow", pkg_name);
+            }
+            bail!("Package {} not found", pkg_name);
+        };
+        Ok(timer.respond(pkg.basic.votes))
+    }
+
+    async fn get_packages_occurences_in_deps(&mut self, pkg_names: &[String], groups: &[String]) -> Result<DbResponse<HashMap<String, u32>>> {
         let mut result: HashMap<String, u32> = HashMap::new();
         pkg_names.iter().for_each(|name| _ = result.insert(name.to_owned(), 0));
-        
-        let start = Instant::now();
-        let pkg_deps_names: Vec<Vec<String>> = self.db.query("SELECT VALUE dependencies.group FROM pkgs").await?.take(0)?;
-        let pkg_deps_names: Vec<String> = pkg_deps_names.into_iter().flatten().collect();
-        for pkg_name in pkg_deps_names {
-            if result.contains_key(&pkg_name) {
-                let count = result.get(&pkg_name).unwrap() + 1;
-                result.insert(pkg_name, count);
+
+        // Grouping leaves one row per group and package listing the name, a repeated edge included
+        let mut query = "SELECT name, kind, in FROM depends_on WHERE name INSIDE $names".to_owned();
+        if !groups.is_empty() {
+            query += " AND kind INSIDE $groups";
+        }
+        query += " GROUP BY name, kind, in";
+
+        let mut timer = PhaseTimer::start();
+        let mut response = self.db.query(query)
+            .bind(("names", pkg_names))
+            .bind(("groups", groups))
+            .await?;
+        timer.round_trip();
+        let listed: Vec<DependencyEdge> = response.take(0)?;
+        timer.deserialization();
+        for edge in listed {
+            if let Some(count) = result.get_mut(&edge.name) {
+                *count += 1;
             }
         }
-        let duration = start.elapsed();
-        Ok(DbResponse { result , duration })
+        Ok(timer.respond(result))
+    }
+
+    fn is_connection_error(&self, error: &anyhow::Error) -> bool {
+        error.chain().any(|cause| {
+            matches!(cause.downcast_ref::<surrealdb::Error>(), Some(surrealdb::Error::Api(Api::Ws(_) | Api::ConnectionUninitialised)))
+                || cause.is::<std::io::Error>()
+        })
     }
 }
 
@@ -133,11 +576,13 @@ mod test {
     use super::SurrealDbClient;
     use anyhow::{Result, Ok};
     use super::DbActions;
+    use crate::{config::ConnectionConfig, commands::Db};
 
     #[tokio::test]
     async fn test_query() -> Result<()> {
-        let mut db = SurrealDbClient::try_new().await?;
-        let result = db.get_packages_occurences_in_deps(&vec!["rust".to_string(), "go".to_string(), "sudo".to_string()]).await?;
+        let profile = ConnectionConfig::default().profile(&Db::SurrealDb, None)?;
+        let mut db = SurrealDbClient::try_new(&profile).await?;
+        let result = db.get_packages_occurences_in_deps(&["rust".to_string(), "go".to_string(), "sudo".to_string()], &[]).await?;
         println!("{:?}", result);
         Ok(())
     }
@@ -0,0 +1,114 @@
+use std::time::{Duration, Instant};
+
+use serde::{Deserialize, Serialize};
+
+use super::DbResponse;
+
+/// Where the time of an operation went. The phases other than `connect` and `server` add up to `DbResponse::duration`.
+#[derive(Serialize, Deserialize, Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Timings {
+    // Connection setup the call waited for before the operation started, zero when a connection was reused
+    pub connect: Duration,
+    // Waiting for the database, from sending requests until their responses are read
+    pub round_trips: Duration,
+    // Execution time reported by the database itself, part of `round_trips`; only some backends report it
+    pub server: Option<Duration>,
+    // Turning responses into Rust values, where this is done apart from reading them
+    pub deserialization: Duration,
+    // Client side work: building queries, serializing arguments, sorting, filtering and aggregating
+    pub processing: Duration,
+    pub round_trip_count: u32,
+}
+
+/// Splits the duration of an operation into phases: every mark charges the time since the previous mark to one phase.
+pub struct PhaseTimer {
+    start: Instant,
+    last: Instant,
+    timings: Timings,
+}
+
+impl PhaseTimer {
+    pub fn start() -> Self {
+        let now = Instant::now();
+        Self { start: now, last: now, timings: Timings::default() }
+    }
+
+    fn lap(&mut self) -> Duration {
+        let now = Instant::now();
+        let elapsed = now - self.last;
+        self.last = now;
+        elapsed
+    }
+
+    pub fn round_trip(&mut self) {
+        self.round_trips(1);
+    }
+
+    /// Charges the time since the previous mark to `count` round-trips, for loops or pipelines of requests.
+    pub fn round_trips(&mut self, count: u32) {
+        let elapsed = self.lap();
+        self.timings.round_trips += elapsed;
+        self.timings.round_trip_count += count;
+    }
+
+    pub fn deserialization(&mut self) {
+        let elapsed = self.lap();
+        self.timings.deserialization += elapsed;
+    }
+
+    pub fn processing(&mut self) {
+        let elapsed = self.lap();
+        self.timings.processing += elapsed;
+    }
+
+    pub fn server(&mut self, duration: Duration) {
+        *self.timings.server.get_or_insert(Duration::ZERO) += duration;
+    }
+
+    /// Adds the phases of a nested operation which ran since the previous mark, the rest of that time is processing.
+    pub fn nested(&mut self, timings: &Timings) {
+        let elapsed = self.lap();
+        let nested = timings.round_trips + timings.deserialization + timings.processing;
+        self.timings.round_trips += timings.round_trips;
+        self.timings.round_trip_count += timings.round_trip_count;
+        self.timings.deserialization += timings.deserialization;
+        self.timings.processing += timings.processing + elapsed.saturating_sub(nested);
+        if let Some(server) = timings.server {
+            self.server(server);
+        }
+    }
+
+    /// Charges the time since the previous mark to processing and builds the response.
+    pub fn respond<T: Serialize>(mut self, result: T) -> DbResponse<T> {
+        self.processing();
+        DbResponse { result, duration: self.last - self.start, timings: self.timings }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{thread::sleep, time::Duration};
+
+    use super::{PhaseTimer, Timings};
+
+    #[test]
+    fn test_phases_add_up() {
+        let mut timer = PhaseTimer::start();
+        sleep(Duration::from_millis(2));
+        timer.round_trips(3);
+        timer.server(Duration::from_millis(1));
+        sleep(Duration::from_millis(1));
+        timer.deserialization();
+        let nested = Timings { round_trips: Duration::from_millis(1), round_trip_count: 2, ..Default::default() };
+        sleep(Duration::from_millis(2));
+        timer.nested(&nested);
+        let response = timer.respond(());
+
+        let timings = response.timings;
+        assert_eq!(timings.round_trip_count, 5);
+        assert_eq!(timings.server, Some(Duration::from_millis(1)));
+        assert!(timings.round_trips >= Duration::from_millis(3));
+        assert!(timings.deserialization >= Duration::from_millis(1));
+        assert_eq!(timings.round_trips + timings.deserialization + timings.processing, response.duration);
+    }
+}
@@ -0,0 +1,315 @@
+use std::{collections::BTreeMap, fs, path::Path, str::FromStr, time::Duration};
+
+use anyhow::{Context, Result, anyhow, bail};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::{commands::Db, history::{HistoryStore, Measurement, Run, RunFilter}};
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ExportFormat {
+    Csv,
+    Json,
+    Markdown,
+    Html,
+}
+
+impl ExportFormat {
+    /// Guesses the format from the file extension, e.g. of the path chosen in the save dialog.
+    pub fn from_path(path: &Path) -> Result<Self> {
+        let extension = path.extension().and_then(|ext| ext.to_str()).unwrap_or_default();
+        extension.parse().with_context(|| format!("Cannot tell the export format of {}", path.display()))
+    }
+}
+
+impl FromStr for ExportFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(value: &str) -> Result<Self> {
+        match value.to_lowercase().as_str() {
+            "csv" => Ok(Self::Csv),
+            "json" => Ok(Self::Json),
+            "md" | "markdown" => Ok(Self::Markdown),
+            "html" | "htm" => Ok(Self::Html),
+            _ => bail!("Unknown export format '{}', expected csv, json, markdown or html", value),
+        }
+    }
+}
+
+const CSV_HEADER: [&str; 29] = [
+    "id", "recorded_at", "db", "profile", "settings", "operation", "arguments", "dataset", "app_version", "os", "arch", "hostname", "kind",
+    "duration_ns", "connect_ns", "round_trips_ns", "server_ns", "deserialization_ns", "processing_ns", "round_trip_count",
+    "iterations", "warmup", "min_ns", "max_ns", "mean_ns", "median_ns", "p95_ns", "p99_ns", "stddev_ns",
+];
+
+pub fn render(runs: &[Run], format: ExportFormat) -> Result<String> {
+    match format {
+        ExportFormat::Csv => Ok(render_csv(runs)),
+        ExportFormat::Json => Ok(serde_json::to_string_pretty(runs)?),
+        ExportFormat::Markdown => Ok(render_markdown(&Report::new(runs))),
+        ExportFormat::Html => Ok(render_html(&Report::new(runs))),
+    }
+}
+
+/// Writes the runs to `path` in the given format, guessed from the extension if not given; returns the number of runs.
+pub fn export(runs: &[Run], format: Option<ExportFormat>, path: &Path) -> Result<usize> {
+    let format = match format {
+        Some(format) => format,
+        None => ExportFormat::from_path(path)?,
+    };
+    fs::write(path, render(runs, format)?).with_context(|| format!("Cannot write export: {}", path.display()))?;
+    Ok(runs.len())
+}
+
+fn operation_parts(run: &Run) -> (String, String) {
+    let arguments = match &run.arguments {
+        Value::Null => String::new(),
+        arguments => arguments.to_string(),
+    };
+    (run.operation.clone(), arguments)
+}
+
+fn nanos(duration: Duration) -> String {
+    duration.as_nanos().to_string()
+}
+
+// One row per run; columns which do not apply to the kind of measurement are left empty
+fn render_csv(runs: &[Run]) -> String {
+    let mut lines = vec![CSV_HEADER.join(",")];
+    for run in runs {
+        let (operation, arguments) = operation_parts(run);
+        let mut fields = vec![
+            run.id.to_string(), run.recorded_at.to_string(), db_name(run.db), run.profile.clone(),
+            serde_json::to_string(&run.settings).unwrap_or_default(), operation, arguments, run.dataset.clone().unwrap_or_default(),
+            run.app_version.clone(), run.host.os.clone(), run.host.arch.clone(), run.host.hostname.clone().unwrap_or_default(),
+        ];
+        match &run.measurement {
+            Measurement::Single { duration, timings } => {
+                fields.extend(["single".to_owned(), nanos(*duration)]);
+                match timings {
+                    Some(timings) => fields.extend([
+                        nanos(timings.connect), nanos(timings.round_trips), timings.server.map(nanos).unwrap_or_default(),
+                        nanos(timings.deserialization), nanos(timings.processing), timings.round_trip_count.to_string(),
+                    ]),
+                    None => fields.extend(vec![String::new(); 6]),
+                }
+                fields.extend(vec![String::new(); 9]);
+            },
+            Measurement::Benchmark { iterations, warmup, stats } => {
+                fields.push("benchmark".to_owned());
+                fields.extend(vec![String::new(); 7]);
+                fields.extend([iterations.to_string(), warmup.to_string()]);
+                fields.extend([stats.min, stats.max, stats.mean, stats.median, stats.p95, stats.p99, stats.stddev].map(nanos));
+            },
+        }
+        lines.push(fields.iter().map(|field| csv_field(field)).collect::<Vec<_>>().join(","));
+    }
+    lines.join("\n") + "\n"
+}
+
+fn csv_field(field: &str) -> String {
+    match field.contains([',', '"', '\n', '\r']) {
+        true => format!("\"{}\"", field.replace('"', "\"\"")),
+        false => field.to_owned(),
+    }
+}
+
+/// The comparison report: one table per operation, arguments and dataset, backends ordered from the fastest.
+struct Report {
+    sections: Vec<Section>,
+}
+
+struct Section {
+    operation: String,
+    arguments: String,
+    dataset: String,
+    rows: Vec<[String; 7]>,
+}
+
+impl Section {
+    // What the runs of the table have in common besides the operation, empty when they have neither
+    fn subtitle(&self) -> String {
+        let mut parts = vec![];
+        if !self.arguments.is_empty() {
+            parts.push(format!("Arguments: {}", self.arguments));
+        }
+        if !self.dataset.is_empty() {
+            parts.push(format!("Dataset: {}", self.dataset));
+        }
+        parts.join(", ")
+    }
+}
+
+const REPORT_COLUMNS: [&str; 7] = ["Backend", "Profile", "Kind", "Duration", "p95", "Round-trips", "vs fastest"];
+
+impl Report {
+    fn new(runs: &[Run]) -> Self {
+        // Only runs doing the same work on the same data are compared with each other
+        let mut by_work: BTreeMap<(String, String, String), Vec<&Run>> = BTreeMap::new();
+        for run in runs {
+            let (operation, arguments) = operation_parts(run);
+            by_work.entry((operation, arguments, run.dataset.clone().unwrap_or_default())).or_default().push(run);
+        }
+
+        let sections = by_work.into_iter().map(|((operation, arguments, dataset), mut runs)| {
+            runs.sort_by_key(|run| run.measurement.headline());
+            let fastest = runs[0].measurement.headline().as_secs_f64();
+            let rows = runs.into_iter().map(|run| {
+                let headline = run.measurement.headline();
+                let (kind, p95, round_trips) = match &run.measurement {
+                    Measurement::Single { timings, .. } =>
+                        ("single".to_owned(), String::new(), timings.map(|timings| timings.round_trip_count.to_string()).unwrap_or_default()),
+                    Measurement::Benchmark { iterations, stats, .. } =>
+                        (format!("median of {}", iterations), format_duration(stats.p95), String::new()),
+                };
+                let relative = match fastest > 0.0 {
+                    true => format!("{:.2}x", headline.as_secs_f64() / fastest),
+                    false => String::new(),
+                };
+                [db_name(run.db), run.profile.clone(), kind, format_duration(headline), p95, round_trips, relative]
+            }).collect();
+            Section { operation, arguments, dataset, rows }
+        }).collect();
+        Self { sections }
+    }
+}
+
+fn db_name(db: Db) -> String {
+    format!("{:?}", db)
+}
+
+fn format_duration(duration: Duration) -> String {
+    format!("{:.3} ms", duration.as_secs_f64() * 1000.0)
+}
+
+fn render_markdown(report: &Report) -> String {
+    let escape = |cell: &str| cell.replace('|', "\\|").replace('\n', " ");
+    let mut out = String::from("# Benchmark report\n");
+    for section in &report.sections {
+        out += &format!("\n## {}\n\n", section.operation);
+        let subtitle = section.subtitle();
+        if !subtitle.is_empty() {
+            out += &format!("{}\n\n", escape(&subtitle));
+        }
+        out += &format!("| {} |\n", REPORT_COLUMNS.join(" | "));
+        out += &format!("|{}\n", "---|".repeat(REPORT_COLUMNS.len()));
+        for row in &section.rows {
+            out += &format!("| {} |\n", row.iter().map(|cell| escape(cell)).collect::<Vec<_>>().join(" | "));
+        }
+    }
+    out
+}
+
+fn render_html(report: &Report) -> String {
+    let escape = |cell: &str| cell.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;");
+    let mut out = String::from("<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>Benchmark report</title>\n</head>\n<body>\n<h1>Benchmark report</h1>\n");
+    for section in &report.sections {
+        out += &format!("<h2>{}</h2>\n", escape(&section.operation));
+        let subtitle = section.subtitle();
+        if !subtitle.is_empty() {
+            out += &format!("<p>{}</p>\n", escape(&subtitle));
+        }
+        out += "<table>\n<tr>";
+        for column in REPORT_COLUMNS {
+            out += &format!("<th>{}</th>", column);
+        }
+        out += "</tr>\n";
+        for row in &section.rows {
+            out += "<tr>";
+            for cell in row {
+                out += &format!("<td>{}</td>", escape(cell));
+            }
+            out += "</tr>\n";
+        }
+        out += "</table>\n";
+    }
+    out + "</body>\n</html>\n"
+}
+
+/// Handles `export <path> [--format <format>] [--db <db>] [--operation <name>] [--limit <count>]`, which writes the
+/// run history without starting the window. Returns `None` when the arguments are not an export.
+pub fn run_cli(args: &[String], data_dir: Option<&Path>) -> Option<Result<usize>> {
+    match args {
+        [command, rest @ ..] if command == "export" => Some(export_from_args(rest, data_dir)),
+        _ => None,
+    }
+}
+
+fn export_from_args(args: &[String], data_dir: Option<&Path>) -> Result<usize> {
+    let mut path = None;
+    let mut format = None;
+    let mut filter = RunFilter::default();
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let mut value = || args.next().ok_or(anyhow!("Missing value of {}", arg));
+        match arg.as_str() {
+            "--format" => format = Some(value()?.parse()?),
+            "--db" => filter.db = Some(serde_json::from_value(Value::String(value()?.clone())).with_context(|| format!("Unknown database for {}", arg))?),
+            "--operation" => filter.operation = Some(value()?.clone()),
+            "--limit" => filter.limit = Some(value()?.parse().with_context(|| format!("Invalid number for {}", arg))?),
+            _ if path.is_none() && !arg.starts_with("--") => path = Some(arg.clone()),
+            _ => bail!("Unexpected argument: {}", arg),
+        }
+    }
+    let path = path.ok_or(anyhow!("Usage: export <path> [--format csv|json|markdown|html] [--db <db>] [--operation <name>] [--limit <count>]"))?;
+
+    let runs = HistoryStore::load(data_dir)?.list(&filter)?;
+    export(&runs, format, Path::new(&path))
+}
+
+#[cfg(test)]
+mod test {
+    use std::{path::Path, time::Duration};
+
+    use anyhow::{Result, Ok};
+
+    use super::{ExportFormat, render};
+    use crate::{benchmark::BenchmarkStats, commands::Db, config::ConnectionConfig, database::{Operation, Timings}, history::{HistoryStore, Measurement, RunFilter}};
+
+    #[test]
+    fn test_render() -> Result<()> {
+        let history = HistoryStore::open(Path::new(":memory:"))?;
+        let timings = Timings { round_trip_count: 4, ..Default::default() };
+        let single = Measurement::Single { duration: Duration::from_millis(4), timings: Some(timings) };
+        let config = ConnectionConfig::default();
+        history.record(Db::Redis, "default", &config.profile(&Db::Redis, None)?, &Operation::SearchPkgs { query: "a, \"b\"".into(), limit: 5 }, &single)?;
+        let stats = BenchmarkStats::from_samples(vec![Duration::from_millis(2); 3])?;
+        history.record(Db::Sqlite, "default", &config.profile(&Db::Sqlite, None)?, &Operation::SearchPkgs { query: "a".into(), limit: 5 }, &Measurement::Benchmark { iterations: 3, warmup: 1, stats })?;
+        let runs = history.list(&RunFilter::default())?;
+
+        let csv = render(&runs, ExportFormat::Csv)?;
+        let lines: Vec<&str> = csv.lines().collect();
+        assert_eq!(lines.len(), 3);
+        assert!(lines[0].starts_with("id,recorded_at,db,"));
+        // Arguments holding commas and quotes stay a single field
+        assert!(lines[2].contains(r#","{""limit"":5,""query"":""a, \""b\""""}","#));
+        assert!(lines[2].ends_with(",single,4000000,0,0,,0,0,4,,,,,,,,,"));
+        assert!(lines[1].contains(",benchmark,,,,,,,,3,1,2000000,"));
+        // Settings are kept without the password
+        assert!(lines[2].contains(r#","{""host"":""127.0.0.1"",""port"":6379,""username"":""default"",""password"":null,"#));
+
+        // Runs with other arguments are not compared with each other
+        history.record(Db::Redis, "default", &config.profile(&Db::Redis, None)?, &Operation::SearchPkgs { query: "a".into(), limit: 5 }, &single)?;
+        let runs = history.list(&RunFilter::default())?;
+        let markdown = render(&runs, ExportFormat::Markdown)?;
+        assert_eq!(markdown.matches("## search_pkgs").count(), 2);
+        let sections: Vec<&str> = markdown.split("## ").skip(1).collect();
+        assert!(sections[0].contains(r#"Arguments: {"limit":5,"query":"a"}"#));
+        let rows: Vec<&str> = sections[0].lines().filter(|line| line.starts_with("| Sqlite") || line.starts_with("| Redis")).collect();
+        assert!(rows[0].starts_with("| Sqlite") && rows[0].contains("| 2.000 ms |") && rows[0].contains("| 1.00x |"));
+        assert!(rows[1].starts_with("| Redis") && rows[1].contains("| 2.00x |"));
+        let rows: Vec<&str> = sections[1].lines().filter(|line| line.starts_with("| ")).collect();
+        assert_eq!(rows.len(), 2);
+        assert!(rows[1].starts_with("| Redis") && rows[1].contains("| 1.00x |"));
+
+        let html = render(&runs, ExportFormat::Html)?;
+        assert!(html.contains("<h2>search_pkgs</h2>") && html.contains("&quot;a, \\&quot;b\\&quot;&quot;"));
+
+        let json: serde_json::Value = serde_json::from_str(&render(&runs, ExportFormat::Json)?)?;
+        assert_eq!(json.as_array().map(Vec::len), Some(3));
+        assert_eq!(ExportFormat::from_path(Path::new("report.MD"))?, ExportFormat::Markdown);
+        assert!(ExportFormat::from_path(Path::new("report")).is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,236 @@
+use std::{fs::File, io::{BufWriter, Write}, path::Path};
+
+use anyhow::{Result, bail};
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+use serde::{Deserialize, Serialize};
+
+use crate::models::{PackageData, BasicPackageData, AdditionalPackageData, PackageDependency, Comment};
+
+const WORDS: [&str; 32] = [
+    "python", "rust", "git", "lib", "qt", "gtk", "vim", "font", "theme", "kernel", "driver", "audio",
+    "video", "game", "launcher", "proxy", "client", "server", "cli", "tool", "bin", "docker", "shell", "net",
+    "image", "editor", "wayland", "x11", "nvidia", "bluetooth", "backup", "sync",
+];
+const LICENSES: [&str; 8] = ["GPL3", "GPL2", "MIT", "Apache", "BSD", "LGPL", "MPL2", "custom"];
+const COMMENT_USERS: usize = 500;
+
+/// A distribution of numeric values; all samples are clamped at zero.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Distribution {
+    Constant { value: f64 },
+    Uniform { min: f64, max: f64 },
+    Exponential { mean: f64 },
+    // Heavy tailed, most packages get few votes and a handful gets thousands
+    Pareto { scale: f64, shape: f64 },
+}
+
+impl Distribution {
+    fn sample(&self, rng: &mut ChaCha8Rng) -> f64 {
+        // Open interval (0, 1] keeps the logarithms finite
+        let u = 1.0 - rng.gen::<f64>();
+        let value = match self {
+            Distribution::Constant { value } => *value,
+            Distribution::Uniform { min, max } => min + (max - min) * u,
+            Distribution::Exponential { mean } => -mean * u.ln(),
+            Distribution::Pareto { scale, shape } => scale / u.powf(1.0 / shape),
+        };
+        value.max(0.0)
+    }
+
+    fn sample_count(&self, rng: &mut ChaCha8Rng) -> usize {
+        self.sample(rng).round() as usize
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct DependencyGroupConfig {
+    pub group: String,
+    // Probability that a package has this group at all
+    pub probability: f64,
+    // Number of packages in the group, picked from the packages generated before
+    pub fan_out: Distribution,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct GeneratorConfig {
+    pub seed: u64,
+    pub count: usize,
+    pub votes: Distribution,
+    pub popularity: Distribution,
+    pub comments: Distribution,
+    pub dependency_groups: Vec<DependencyGroupConfig>,
+}
+
+impl Default for GeneratorConfig {
+    fn default() -> Self {
+        Self {
+            seed: 0,
+            count: 1000,
+            votes: Distribution::Pareto { scale: 1.0, shape: 1.1 },
+            popularity: Distribution::Exponential { mean: 0.5 },
+            comments: Distribution::Exponential { mean: 4.0 },
+            dependency_groups: vec![
+                DependencyGroupConfig { group: "depends".into(), probability: 0.9, fan_out: Distribution::Exponential { mean: 3.0 } },
+                DependencyGroupConfig { group: "makedepends".into(), probability: 0.6, fan_out: Distribution::Exponential { mean: 2.0 } },
+                DependencyGroupConfig { group: "optdepends".into(), probability: 0.3, fan_out: Distribution::Uniform { min: 1.0, max: 4.0 } },
+            ],
+        }
+    }
+}
+
+/// Produces AUR-like packages; the same config always yields the same packages, on every platform.
+pub struct Generator {
+    config: GeneratorConfig,
+    rng: ChaCha8Rng,
+    names: Vec<String>,
+}
+
+impl Generator {
+    pub fn new(config: GeneratorConfig) -> Self {
+        let rng = ChaCha8Rng::seed_from_u64(config.seed);
+        Self { config, rng, names: vec![] }
+    }
+
+    // Ranges are sampled as u64, as usize sampling differs between 32 and 64 bit targets
+    fn index(&mut self, len: usize) -> usize {
+        self.rng.gen_range(0..len as u64) as usize
+    }
+
+    fn word(&mut self) -> &'static str {
+        WORDS[self.index(WORDS.len())]
+    }
+
+    fn date(&mut self, from_year: u32) -> String {
+        let year = self.rng.gen_range(from_year..=2023u32);
+        let month = self.rng.gen_range(1..=12u32);
+        let day = self.rng.gen_range(1..=28u32);
+        let hour = self.rng.gen_range(0..24u32);
+        let minute = self.rng.gen_range(0..60u32);
+        format!("{}-{:02}-{:02} {:02}:{:02} (UTC)", year, month, day, hour, minute)
+    }
+
+    fn next_pkg(&mut self) -> PackageData {
+        let idx = self.names.len();
+        let name = format!("{}-{}-{}", self.word(), self.word(), idx);
+        let maintainer_count = (self.config.count / 10).max(1);
+        let maintainer = format!("maintainer-{}", self.index(maintainer_count));
+
+        let votes = self.config.votes.sample(&mut self.rng).min(i32::MAX as f64).round() as i32;
+        let popularity = (self.config.popularity.sample(&mut self.rng) * 100.0).round() as f32 / 100.0;
+        let description = (0..self.rng.gen_range(3..12u32)).map(|_| self.word()).collect::<Vec<_>>().join(" ");
+        let first_submitted = self.date(2010);
+        let last_updated = self.date(2015);
+        let version = format!("{}.{}.{}", self.rng.gen_range(0..10u32), self.rng.gen_range(0..30u32), self.rng.gen_range(0..100u32));
+
+        let keywords = match self.rng.gen_range(0..4u32) {
+            0 => None,
+            n => Some((0..n).map(|_| self.word()).collect::<Vec<_>>().join(" ")),
+        };
+        let license = Some(LICENSES[self.index(LICENSES.len())].to_owned());
+
+        let comments = (0..self.config.comments.sample_count(&mut self.rng))
+            .map(|c| Comment {
+                header: format!("user-{} commented on {}", self.index(COMMENT_USERS), self.date(2015)),
+                content: format!("Comment {} about {}", c, name),
+            })
+            .collect();
+
+        let mut dependencies = vec![];
+        for group_idx in 0..self.config.dependency_groups.len() {
+            let probability = self.config.dependency_groups[group_idx].probability.clamp(0.0, 1.0);
+            if idx == 0 || !self.rng.gen_bool(probability) {
+                continue;
+            }
+            let fan_out = self.config.dependency_groups[group_idx].fan_out.sample_count(&mut self.rng).min(idx);
+            let mut packages: Vec<String> = Vec::with_capacity(fan_out);
+            while packages.len() < fan_out {
+                let pick = self.index(idx);
+                let dep = &self.names[pick];
+                if !packages.contains(dep) {
+                    packages.push(dep.clone());
+                }
+            }
+            dependencies.push(PackageDependency { group: self.config.dependency_groups[group_idx].group.clone(), packages });
+        }
+
+        self.names.push(name.clone());
+
+        PackageData {
+            basic: BasicPackageData {
+                path_to_additional_data: format!("/packages/{}", name),
+                name: name.clone(),
+                version,
+                votes,
+                popularity,
+                description,
+                maintainer: maintainer.clone(),
+                last_updated,
+            },
+            additional: AdditionalPackageData {
+                git_clone_url: format!("https://aur.archlinux.org/{}.git", name),
+                keywords,
+                license,
+                confilcts: None,
+                provides: None,
+                submitter: maintainer,
+                first_submitted,
+            },
+            dependencies,
+            comments,
+        }
+    }
+}
+
+impl Iterator for Generator {
+    type Item = PackageData;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.names.len() >= self.config.count {
+            return None;
+        }
+        Some(self.next_pkg())
+    }
+}
+
+/// Writes the generated packages as a JSON-lines file, the format read by the dataset importer.
+pub fn write_dataset(config: GeneratorConfig, path: &Path) -> Result<usize> {
+    if config.dependency_groups.iter().any(|g| !(0.0..=1.0).contains(&g.probability)) {
+        bail!("Dependency group probability must be between 0 and 1");
+    }
+
+    let mut writer = BufWriter::new(File::create(path)?);
+    let mut count = 0;
+    for pkg in Generator::new(config) {
+        serde_json::to_writer(&mut writer, &pkg)?;
+        writer.write_all(b"\n")?;
+        count += 1;
+    }
+    writer.flush()?;
+    Ok(count)
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Generator, GeneratorConfig};
+
+    #[test]
+    fn test_generator() {
+        let config = GeneratorConfig { seed: 42, count: 200, ..Default::default() };
+        let first: Vec<_> = Generator::new(config.clone()).collect();
+        let second: Vec<_> = Generator::new(config.clone()).collect();
+        let other: Vec<_> = Generator::new(GeneratorConfig { seed: 43, ..config }).collect();
+
+        assert_eq!(first.len(), 200);
+        assert_eq!(first, second);
+        assert_ne!(first, other);
+
+        let names: Vec<&String> = first.iter().map(|pkg| &pkg.basic.name).collect();
+        for (idx, pkg) in first.iter().enumerate() {
+            for dep in pkg.dependencies.iter().flat_map(|d| &d.packages) {
+                assert!(names[..idx].contains(&dep));
+            }
+        }
+    }
+}
@@ -0,0 +1,422 @@
+use std::{env, fs, path::{Path, PathBuf}, sync::Mutex, time::{Duration, SystemTime, UNIX_EPOCH}};
+
+use anyhow::{Context, Result, anyhow, bail};
+use rusqlite::{Connection, OptionalExtension, Row, params, params_from_iter, types::Value as SqlValue};
+use serde::{Deserialize, Serialize};
+use serde_json::{Value, json};
+
+use crate::{benchmark::BenchmarkStats, commands::Db, config::ConnectionProfile, database::{DbResponse, Operation, Timings}, models::PackageData};
+
+const HISTORY_PATH_VAR: &str = "ZTPD_HISTORY";
+const HISTORY_FILE_NAME: &str = "history.sqlite";
+
+const SCHEMA: &str = "
+    CREATE TABLE IF NOT EXISTS runs (
+        id INTEGER PRIMARY KEY,
+        recorded_at INTEGER NOT NULL,
+        db TEXT NOT NULL,
+        profile TEXT NOT NULL,
+        settings TEXT NOT NULL,
+        operation_name TEXT NOT NULL,
+        arguments TEXT NOT NULL,
+        dataset TEXT,
+        app_version TEXT NOT NULL,
+        host TEXT NOT NULL,
+        measurement TEXT NOT NULL
+    );
+    CREATE INDEX IF NOT EXISTS runs_recorded_at_idx ON runs(recorded_at);
+    CREATE INDEX IF NOT EXISTS runs_db_operation_idx ON runs(db, operation_name);
+
+    CREATE TABLE IF NOT EXISTS datasets (
+        db TEXT NOT NULL,
+        profile TEXT NOT NULL,
+        fingerprint TEXT NOT NULL,
+        PRIMARY KEY (db, profile)
+    );
+";
+
+const RUN_COLUMNS: &str = "id, recorded_at, db, profile, settings, operation_name, arguments, dataset, app_version, host, measurement";
+
+/// The machine a run was made on, the databases themselves may run elsewhere.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct HostInfo {
+    pub os: String,
+    pub arch: String,
+    pub hostname: Option<String>,
+    pub cpus: usize,
+}
+
+impl HostInfo {
+    pub fn current() -> Self {
+        let hostname = env::var("HOSTNAME")
+            .or_else(|_| env::var("COMPUTERNAME"))
+            .ok()
+            .or_else(|| fs::read_to_string("/etc/hostname").ok().map(|name| name.trim().to_owned()))
+            .filter(|name| !name.is_empty());
+        Self {
+            os: env::consts::OS.to_owned(),
+            arch: env::consts::ARCH.to_owned(),
+            hostname,
+            cpus: std::thread::available_parallelism().map_or(1, |cpus| cpus.get()),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Measurement {
+    // A single call; `timings` is `None` for calls which only report their duration
+    Single { duration: Duration, timings: Option<Timings> },
+    Benchmark { iterations: u32, warmup: u32, stats: BenchmarkStats },
+}
+
+impl Measurement {
+    pub fn of_response<T: Serialize>(response: &DbResponse<T>) -> Self {
+        Measurement::Single { duration: response.duration, timings: Some(response.timings) }
+    }
+
+    /// The duration runs are compared by, the median for benchmarks as it is the least affected by outliers.
+    pub fn headline(&self) -> Duration {
+        match self {
+            Measurement::Single { duration, .. } => *duration,
+            Measurement::Benchmark { stats, .. } => stats.median,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Run {
+    pub id: i64,
+    // Milliseconds since the Unix epoch
+    pub recorded_at: u64,
+    pub db: Db,
+    pub profile: String,
+    // The effective settings of the profile at the time of the run, without the password
+    pub settings: ConnectionProfile,
+    // Name of the operation, e.g. `get_pkg`
+    pub operation: String,
+    // Packages written by inserts are summarized by their count and first and last names
+    pub arguments: Value,
+    // Fingerprint of the dataset last imported into the backend, `None` if nothing was imported through the app
+    pub dataset: Option<String>,
+    pub app_version: String,
+    pub host: HostInfo,
+    pub measurement: Measurement,
+}
+
+/// Conditions a listed run has to meet, unset ones match every run.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(default)]
+pub struct RunFilter {
+    pub db: Option<Db>,
+    pub profile: Option<String>,
+    // Name of the operation, e.g. `get_pkg`
+    pub operation: Option<String>,
+    pub dataset: Option<String>,
+    pub app_version: Option<String>,
+    // Bounds of `recorded_at`, both inclusive
+    pub since: Option<u64>,
+    pub until: Option<u64>,
+    pub limit: Option<u32>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct RunDelta {
+    pub run: Run,
+    pub duration: Duration,
+    // Relative to the baseline, e.g. `0.25` for a run 25% slower
+    pub change: f64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct RunComparison {
+    pub baseline: i64,
+    pub runs: Vec<RunDelta>,
+    // Differing operations, datasets or settings make the numbers hard to compare, the frontend warns about it
+    pub same_operation: bool,
+    pub same_dataset: bool,
+    pub same_settings: bool,
+}
+
+/// Keeps every timed operation in an embedded SQLite database, so runs can be compared across versions and configurations.
+pub struct HistoryStore {
+    connection: Mutex<Connection>,
+}
+
+impl HistoryStore {
+    /// Opens the store at the path given by `ZTPD_HISTORY`, `history.sqlite` in the app data directory by default.
+    /// The working directory of a bundled app is often read-only, so it is never used.
+    pub fn load(data_dir: Option<&Path>) -> Result<Self> {
+        let path = match env::var(HISTORY_PATH_VAR) {
+            Ok(path) => PathBuf::from(path),
+            Err(_) => {
+                let data_dir = data_dir.ok_or(anyhow!("No app data directory to keep the benchmark history in"))?;
+                fs::create_dir_all(data_dir)
+                    .with_context(|| format!("Cannot create app data directory: {}", data_dir.display()))?;
+                data_dir.join(HISTORY_FILE_NAME)
+            },
+        };
+        Self::open(&path)
+    }
+
+    /// A store which lives as long as the process, for when the history file cannot be opened.
+    pub fn in_memory() -> Result<Self> {
+        Self::open(Path::new(":memory:"))
+    }
+
+    pub fn open(path: &Path) -> Result<Self> {
+        let connection = Connection::open(path)
+            .with_context(|| format!("Cannot open benchmark history: {}", path.display()))?;
+        connection.execute_batch(SCHEMA)?;
+        Ok(Self { connection: Mutex::new(connection) })
+    }
+
+    fn connection(&self) -> Result<std::sync::MutexGuard<'_, Connection>> {
+        self.connection.lock().map_err(|_| anyhow!("Benchmark history is poisoned"))
+    }
+
+    /// Stores a run made now on this host with the given profile settings, returning its id.
+    pub fn record(&self, db: Db, profile: &str, settings: &ConnectionProfile, operation: &Operation, measurement: &Measurement) -> Result<i64> {
+        let settings = ConnectionProfile { password: None, ..settings.clone() };
+        let (operation_name, arguments) = recorded_operation(operation)?;
+        let connection = self.connection()?;
+        connection.execute(
+            "INSERT INTO runs (recorded_at, db, profile, settings, operation_name, arguments, dataset, app_version, host, measurement)
+                VALUES (?1, ?2, ?3, ?4, ?5, ?6, (SELECT fingerprint FROM datasets WHERE db = ?2 AND profile = ?3), ?7, ?8, ?9)",
+            params![
+                now_millis() as i64,
+                db_key(db)?,
+                profile,
+                serde_json::to_string(&settings)?,
+                operation_name,
+                arguments.to_string(),
+                env!("CARGO_PKG_VERSION"),
+                serde_json::to_string(&HostInfo::current())?,
+                serde_json::to_string(measurement)?,
+            ],
+        )?;
+        Ok(connection.last_insert_rowid())
+    }
+
+    /// Remembers the dataset a backend was loaded with, later runs against it are tagged with the fingerprint.
+    pub fn set_dataset(&self, db: Db, profile: &str, fingerprint: &str) -> Result<()> {
+        self.connection()?.execute(
+            "INSERT INTO datasets (db, profile, fingerprint) VALUES (?1, ?2, ?3)
+                ON CONFLICT (db, profile) DO UPDATE SET fingerprint = excluded.fingerprint",
+            params![db_key(db)?, profile, fingerprint],
+        )?;
+        Ok(())
+    }
+
+    /// Runs matching the filter, most recent first.
+    pub fn list(&self, filter: &RunFilter) -> Result<Vec<Run>> {
+        let mut conditions = vec![];
+        let mut values: Vec<SqlValue> = vec![];
+        let mut condition = |column: &str, value: SqlValue| {
+            conditions.push(format!("{} ?{}", column, conditions.len() + 1));
+            values.push(value);
+        };
+        if let Some(db) = filter.db {
+            condition("db =", SqlValue::Text(db_key(db)?));
+        }
+        let text_filters = [("profile =", &filter.profile), ("operation_name =", &filter.operation), ("dataset =", &filter.dataset), ("app_version =", &filter.app_version)];
+        for (column, value) in text_filters {
+            if let Some(value) = value {
+                condition(column, SqlValue::Text(value.clone()));
+            }
+        }
+        for (column, value) in [("recorded_at >=", filter.since), ("recorded_at <=", filter.until)] {
+            if let Some(value) = value {
+                condition(column, SqlValue::Integer(value as i64));
+            }
+        }
+
+        let mut query = format!("SELECT {} FROM runs", RUN_COLUMNS);
+        if !conditions.is_empty() {
+            query += &format!(" WHERE {}", conditions.join(" AND "));
+        }
+        query += " ORDER BY recorded_at DESC, id DESC";
+        if let Some(limit) = filter.limit {
+            query += &format!(" LIMIT {}", limit);
+        }
+
+        let connection = self.connection()?;
+        let mut statement = connection.prepare(&query)?;
+        let rows = statement.query_map(params_from_iter(values), read_run)?;
+        rows.map(|row| row?).collect()
+    }
+
+    pub fn get(&self, id: i64) -> Result<Run> {
+        let connection = self.connection()?;
+        let run = connection
+            .query_row(&format!("SELECT {} FROM runs WHERE id = ?1", RUN_COLUMNS), [id], read_run)
+            .optional()?;
+        match run {
+            Some(run) => run,
+            None => bail!("Run {} not found", id),
+        }
+    }
+
+    /// Deletes the given runs, returning how many existed.
+    pub fn delete(&self, ids: &[i64]) -> Result<usize> {
+        let connection = self.connection()?;
+        let mut statement = connection.prepare("DELETE FROM runs WHERE id = ?1")?;
+        let mut deleted = 0;
+        for id in ids {
+            deleted += statement.execute([id])?;
+        }
+        Ok(deleted)
+    }
+
+    /// Compares runs against the first one given.
+    pub fn compare(&self, ids: &[i64]) -> Result<RunComparison> {
+        let Some(baseline) = ids.first() else {
+            bail!("No runs to compare");
+        };
+        let runs = ids.iter().map(|id| self.get(*id)).collect::<Result<Vec<Run>>>()?;
+        let baseline_nanos = runs[0].measurement.headline().as_nanos() as f64;
+        let settings = serde_json::to_value(&runs[0].settings)?;
+
+        let mut same_operation = true;
+        let mut same_dataset = true;
+        let mut same_settings = true;
+        for run in &runs {
+            same_operation &= run.operation == runs[0].operation && run.arguments == runs[0].arguments;
+            same_dataset &= run.dataset == runs[0].dataset;
+            same_settings &= serde_json::to_value(&run.settings)? == settings;
+        }
+
+        let runs = runs.into_iter().map(|run| {
+            let duration = run.measurement.headline();
+            let change = match baseline_nanos > 0.0 {
+                true => duration.as_nanos() as f64 / baseline_nanos - 1.0,
+                false => 0.0,
+            };
+            RunDelta { run, duration, change }
+        }).collect();
+        Ok(RunComparison { baseline: *baseline, runs, same_operation, same_dataset, same_settings })
+    }
+}
+
+// `Db` is stored under its serialized name, the one the frontend uses too
+fn db_key(db: Db) -> Result<String> {
+    match serde_json::to_value(db)? {
+        Value::String(key) => Ok(key),
+        other => bail!("Unexpected key for {:?}: {}", db, other),
+    }
+}
+
+// Name and arguments of an operation as stored; the packages of an insert can be a whole dataset, so only a summary is kept
+fn recorded_operation(operation: &Operation) -> Result<(String, Value)> {
+    let summary = |pkgs: &[PackageData]| json!({
+        "count": pkgs.len(),
+        "first": pkgs.first().map(|pkg| &pkg.basic.name),
+        "last": pkgs.last().map(|pkg| &pkg.basic.name),
+    });
+    let summary = match operation {
+        Operation::InsertPkg { pkg } => Some(summary(std::slice::from_ref(pkg.as_ref()))),
+        Operation::InsertPkgs { pkgs } => Some(summary(pkgs)),
+        _ => None,
+    };
+    let Value::Object(mut operation) = serde_json::to_value(operation)? else {
+        bail!("Operation does not serialize to an object");
+    };
+    let name = match operation.remove("name") {
+        Some(Value::String(name)) => name,
+        other => bail!("Unexpected operation name: {:?}", other),
+    };
+    let arguments = summary.unwrap_or_else(|| operation.remove("args").unwrap_or(Value::Null));
+    Ok((name, arguments))
+}
+
+fn now_millis() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |since| since.as_millis() as u64)
+}
+
+// Columns are read in the order of `RUN_COLUMNS`; a row that does not parse fails the listing instead of being skipped
+fn read_run(row: &Row) -> rusqlite::Result<Result<Run>> {
+    let id: i64 = row.get(0)?;
+    let recorded_at: i64 = row.get(1)?;
+    let db: String = row.get(2)?;
+    let profile: String = row.get(3)?;
+    let settings: String = row.get(4)?;
+    let operation: String = row.get(5)?;
+    let arguments: String = row.get(6)?;
+    let dataset: Option<String> = row.get(7)?;
+    let app_version: String = row.get(8)?;
+    let host: String = row.get(9)?;
+    let measurement: String = row.get(10)?;
+
+    let parse = || -> Result<Run> {
+        Ok(Run {
+            id,
+            recorded_at: recorded_at as u64,
+            db: serde_json::from_value(Value::String(db))?,
+            profile,
+            settings: serde_json::from_str(&settings)?,
+            operation,
+            arguments: serde_json::from_str(&arguments)?,
+            dataset,
+            app_version,
+            host: serde_json::from_str(&host)?,
+            measurement: serde_json::from_str(&measurement)?,
+        })
+    };
+    Ok(parse().with_context(|| format!("Cannot read run {}", id)))
+}
+
+#[cfg(test)]
+mod test {
+    use std::{path::Path, time::Duration};
+
+    use anyhow::{Result, Ok};
+
+    use serde_json::json;
+
+    use super::{HistoryStore, Measurement, RunFilter};
+    use crate::{benchmark::BenchmarkStats, commands::Db, config::ConnectionConfig, database::{Operation, Timings, conformance::fixture_pkgs}};
+
+    #[test]
+    fn test_history() -> Result<()> {
+        let history = HistoryStore::open(Path::new(":memory:"))?;
+        let get_pkg = Operation::GetPkg { name: "pkg-1".into() };
+        let single = |millis| Measurement::Single { duration: Duration::from_millis(millis), timings: Some(Timings::default()) };
+        let config = ConnectionConfig::default();
+        let redis_settings = config.profile(&Db::Redis, None)?;
+        let sqlite_settings = config.profile(&Db::Sqlite, None)?;
+
+        let first = history.record(Db::Redis, "default", &redis_settings, &get_pkg, &single(10))?;
+        history.set_dataset(Db::Redis, "default", "abc")?;
+        let second = history.record(Db::Redis, "default", &redis_settings, &get_pkg, &single(15))?;
+        let stats = BenchmarkStats::from_samples((1..=5).map(Duration::from_millis).collect())?;
+        let benchmark = history.record(Db::Sqlite, "default", &sqlite_settings, &Operation::GetCatalogStats, &Measurement::Benchmark { iterations: 5, warmup: 0, stats })?;
+
+        assert_eq!(history.list(&RunFilter::default())?.len(), 3);
+        let run = history.get(first)?;
+        assert_eq!((run.operation.as_str(), &run.arguments), ("get_pkg", &json!({ "name": "pkg-1" })));
+        assert_eq!(run.settings.host, redis_settings.host);
+        assert!(redis_settings.password.is_some() && run.settings.password.is_none());
+        let redis = history.list(&RunFilter { db: Some(Db::Redis), ..Default::default() })?;
+        assert_eq!(redis.iter().map(|run| run.id).collect::<Vec<_>>(), vec![second, first]);
+        assert_eq!(redis[0].dataset.as_deref(), Some("abc"));
+        assert_eq!(redis[1].dataset, None);
+        let filter = RunFilter { operation: Some("get_catalog_stats".into()), ..Default::default() };
+        assert_eq!(history.list(&filter)?[0].id, benchmark);
+        assert_eq!(history.list(&RunFilter { limit: Some(1), ..Default::default() })?.len(), 1);
+
+        let comparison = history.compare(&[first, second])?;
+        assert!(comparison.same_operation && !comparison.same_dataset && comparison.same_settings);
+        assert!(!history.compare(&[first, benchmark])?.same_settings);
+        assert_eq!(comparison.runs[1].change, 0.5);
+        assert_eq!(history.compare(&[first, benchmark])?.runs[1].duration, Duration::from_millis(3));
+        assert!(history.compare(&[first, 1000]).is_err());
+
+        // Inserted packages are summarized instead of stored
+        let insert = history.record(Db::Redis, "default", &redis_settings, &Operation::InsertPkgs { pkgs: fixture_pkgs() }, &single(20))?;
+        assert_eq!(history.get(insert)?.arguments, json!({ "count": 5, "first": "pkg-0", "last": "pkg-4" }));
+
+        assert_eq!(history.delete(&[first, 1000])?, 1);
+        assert!(history.get(first).is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,210 @@
+use std::{fs, path::Path, time::{Duration, Instant}};
+
+use anyhow::{Context, Result, anyhow};
+use serde::{Deserialize, Serialize};
+
+use crate::{commands::Db, database::{BackendRegistry, DbActions}, models::PackageData};
+
+const PROGRESS_STEP: usize = 100;
+const MAX_REPORTED_ERRORS: usize = 20;
+
+/// Packages read from a directory of `*.json` files (one `PackageData` per file) or a JSON-lines file.
+pub struct Dataset {
+    pub pkgs: Vec<PackageData>,
+    // Records which could not be read or parsed
+    pub errors: Vec<String>,
+}
+
+impl Dataset {
+    pub fn read(path: &Path) -> Result<Self> {
+        let mut dataset = Dataset { pkgs: vec![], errors: vec![] };
+
+        if path.is_dir() {
+            let mut files: Vec<_> = fs::read_dir(path)
+                .with_context(|| format!("Cannot read dataset directory: {}", path.display()))?
+                .filter_map(|entry| entry.ok().map(|e| e.path()))
+                .filter(|p| p.extension().is_some_and(|ext| ext == "json"))
+                .collect();
+            files.sort();
+
+            for file in files {
+                let record = fs::read_to_string(&file)
+                    .map_err(anyhow::Error::from)
+                    .and_then(|content| Ok(serde_json::from_str(&content)?));
+                dataset.push(record, &file.display().to_string());
+            }
+        } else {
+            let content = fs::read_to_string(path)
+                .with_context(|| format!("Cannot read dataset file: {}", path.display()))?;
+            for (idx, line) in content.lines().enumerate().filter(|(_, line)| !line.trim().is_empty()) {
+                let record = serde_json::from_str(line).map_err(anyhow::Error::from);
+                dataset.push(record, &format!("line {}", idx + 1));
+            }
+        }
+
+        Ok(dataset)
+    }
+
+    /// Identifies the packages of the dataset, so runs against the same data can be told apart from others.
+    /// FNV-1a over the serialized packages, stable across builds unlike the hashers of the standard library.
+    pub fn fingerprint(&self) -> Result<String> {
+        let mut hash: u64 = 0xcbf29ce484222325;
+        for pkg in &self.pkgs {
+            for byte in serde_json::to_vec(pkg)? {
+                hash = (hash ^ byte as u64).wrapping_mul(0x100000001b3);
+            }
+        }
+        Ok(format!("{:016x}-{}", hash, self.pkgs.len()))
+    }
+
+    fn push(&mut self, record: Result<PackageData>, source: &str) {
+        match record {
+            Ok(pkg) => self.pkgs.push(pkg),
+            Err(e) => self.errors.push(format!("{}: {}", source, e)),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ImportProgress {
+    pub db: Db,
+    pub processed: usize,
+    pub failed: usize,
+    pub total: usize,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ImportReport {
+    pub db: Db,
+    pub imported: usize,
+    pub failed: usize,
+    // Sum of the durations reported by the database for every insert
+    pub load_time: Duration,
+    // Wall clock time of the whole import, including client side work
+    pub elapsed: Duration,
+    pub errors: Vec<String>,
+}
+
+/// Inserts every package of the dataset into a single backend, reporting progress every `PROGRESS_STEP` packages.
+/// With a `batch_size` above one the packages are written with `insert_pkgs`; a failed batch counts all of its packages as failed.
+pub async fn load_into(db: Db, backend: &mut (dyn DbActions + Send), pkgs: &[PackageData], batch_size: usize, on_progress: &(dyn Fn(ImportProgress) + Send + Sync)) -> ImportReport {
+    let start = Instant::now();
+    let mut report = ImportReport { db, imported: 0, failed: 0, load_time: Duration::ZERO, elapsed: Duration::ZERO, errors: vec![] };
+    let mut processed = 0;
+
+    for chunk in pkgs.chunks(batch_size.max(1)) {
+        let response = match chunk {
+            [pkg] => backend.insert_pkg(pkg).await,
+            _ => backend.insert_pkgs(chunk).await,
+        };
+        match response {
+            Ok(response) => {
+                report.imported += chunk.len();
+                report.load_time += response.duration;
+            },
+            Err(e) => {
+                report.failed += chunk.len();
+                if report.errors.len() < MAX_REPORTED_ERRORS {
+                    report.errors.push(format!("{}: {}", describe_chunk(chunk), e));
+                }
+            },
+        }
+
+        let previous = processed;
+        processed += chunk.len();
+        if processed / PROGRESS_STEP > previous / PROGRESS_STEP || processed == pkgs.len() {
+            on_progress(ImportProgress { db, processed, failed: report.failed, total: pkgs.len() });
+        }
+    }
+
+    report.elapsed = start.elapsed();
+    report
+}
+
+fn describe_chunk(chunk: &[PackageData]) -> String {
+    match chunk {
+        [pkg] => pkg.basic.name.clone(),
+        [first, .., last] => format!("{}..{}", first.basic.name, last.basic.name),
+        [] => String::new(),
+    }
+}
+
+/// Loads the dataset into every target backend, one after another; packages already stored are not removed first.
+/// A backend which cannot be connected to gets a report with every package failed, the others are still loaded.
+pub async fn import(registry: &BackendRegistry, dataset: &Dataset, targets: Vec<Db>, profile: Option<&str>, batch_size: usize, on_progress: &(dyn Fn(ImportProgress) + Send + Sync)) -> Vec<ImportReport> {
+    let mut reports = Vec::new();
+    for db in targets {
+        let mut report = match registry.acquire(db, profile).await {
+            Ok(mut backend) => {
+                let report = load_into(db, &mut **backend, &dataset.pkgs, batch_size, on_progress).await;
+                // A failed insert may have broken the connection, the next command then reconnects
+                let loaded = match report.failed {
+                    0 => Ok(()),
+                    failed => Err(anyhow!("{} packages failed to load", failed)),
+                };
+                _ = backend.check(loaded);
+                report
+            },
+            Err(e) => ImportReport {
+                db,
+                imported: 0,
+                failed: dataset.pkgs.len(),
+                load_time: Duration::ZERO,
+                elapsed: Duration::ZERO,
+                errors: vec![format!("Cannot connect: {}", e)],
+            },
+        };
+        report.failed += dataset.errors.len();
+        report.errors.extend(dataset.errors.iter().take(MAX_REPORTED_ERRORS).cloned());
+        reports.push(report);
+    }
+    reports
+}
+
+#[cfg(test)]
+mod test {
+    use std::{fs, sync::Mutex};
+
+    use anyhow::{Result, Ok};
+
+    use super::{Dataset, import, load_into};
+    use crate::{commands::Db, config::ConnectionConfig, database::{BackendRegistry, InMemoryDb, DbActions, conformance::fixture_pkgs}};
+
+    #[tokio::test]
+    async fn test_import() -> Result<()> {
+        let path = std::env::temp_dir().join(format!("ztpd-import-{}.jsonl", std::process::id()));
+        let mut content: Vec<String> = fixture_pkgs().iter().map(serde_json::to_string).collect::<Result<_, _>>()?;
+        content.push("{ not a package }".into());
+        fs::write(&path, content.join("\n"))?;
+
+        let dataset = Dataset::read(&path)?;
+        fs::remove_file(&path)?;
+        assert_eq!(dataset.pkgs.len(), 5);
+        assert_eq!(dataset.errors.len(), 1);
+        // Unreadable records do not take part in the fingerprint
+        let fingerprint = dataset.fingerprint()?;
+        assert!(fingerprint.ends_with("-5"));
+        assert_eq!(Dataset { pkgs: fixture_pkgs(), errors: vec![] }.fingerprint()?, fingerprint);
+
+        let mut db = InMemoryDb::new();
+        let progress = Mutex::new(vec![]);
+        let report = load_into(Db::InMemory, &mut db, &dataset.pkgs, 1, &|p| progress.lock().unwrap().push(p.processed)).await;
+        assert_eq!(report.imported, 5);
+        assert_eq!(report.failed, 0);
+        assert_eq!(*progress.lock().unwrap(), vec![5]);
+        assert_eq!(db.get_pkg("pkg-3").await?.result, dataset.pkgs[3]);
+
+        let mut db = InMemoryDb::new();
+        let report = load_into(Db::InMemory, &mut db, &dataset.pkgs, 2, &|_| {}).await;
+        assert_eq!(report.imported, 5);
+        assert_eq!(db.get_pkg("pkg-4").await?.result, dataset.pkgs[4]);
+
+        // A backend which cannot be acquired fails on its own
+        let registry = BackendRegistry::new(ConnectionConfig::default()).register::<InMemoryDb>(Db::InMemory);
+        let reports = import(&registry, &dataset, vec![Db::Redis, Db::InMemory], None, 1, &|_| {}).await;
+        assert_eq!((reports[0].imported, reports[0].failed), (0, 6));
+        assert!(reports[0].errors[0].starts_with("Cannot connect"));
+        assert_eq!((reports[1].imported, reports[1].failed), (5, 1));
+        Ok(())
+    }
+}
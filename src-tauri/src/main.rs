@@ -5,9 +5,49 @@ mod menu;
 mod commands;
 mod database;
 mod models;
+mod config;
+mod benchmark;
+mod comparison;
+mod import;
+mod generator;
+mod history;
+mod export;
+
+use commands::Db;
+use database::{BackendRegistry, RedisDb, SkytableClient, SurrealDbClient, SqliteDb, InMemoryDb};
 
 fn main() {
+    let context = tauri::generate_context!();
+    let data_dir = tauri::api::path::app_data_dir(context.config());
+
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if let Some(exported) = export::run_cli(&args, data_dir.as_deref()) {
+        match exported {
+            Ok(count) => println!("Exported {} runs", count),
+            Err(e) => {
+                eprintln!("Export failed: {:#}", e);
+                std::process::exit(1);
+            },
+        }
+        return;
+    }
+
+    let config = config::ConnectionConfig::load().expect("error while loading connection profiles");
+    let registry = BackendRegistry::new(config)
+        .register::<SurrealDbClient>(Db::SurrealDb)
+        .register::<RedisDb>(Db::Redis)
+        .register::<SkytableClient>(Db::Skytable)
+        .register::<SqliteDb>(Db::Sqlite)
+        .register::<InMemoryDb>(Db::InMemory);
+    // Losing the history is no reason to keep the app from starting, runs are then kept until it closes
+    let history = history::HistoryStore::load(data_dir.as_deref()).unwrap_or_else(|e| {
+        eprintln!("Cannot open benchmark history, runs will not be kept: {:#}", e);
+        history::HistoryStore::in_memory().expect("error while creating in-memory benchmark history")
+    });
+
     tauri::Builder::default()
+        .manage(registry)
+        .manage(history)
         .menu(menu::build())
         .on_menu_event(|event| menu::handle_menu_event(event))
         .invoke_handler(tauri::generate_handler![
@@ -16,10 +56,31 @@ fn main() {
             commands::get_query_time,
             commands::get_most_voted_pkgs,
             commands::insert_pkg,
+            commands::insert_pkgs,
             commands::get_pkg,
             commands::remove_comments,
-            commands::get_packages_occurences_in_deps
+            commands::delete_pkg,
+            commands::get_reverse_dependencies,
+            commands::resolve_dependency_tree,
+            commands::search_pkgs,
+            commands::get_pkgs_by_maintainer,
+            commands::get_pkgs_by_submitter,
+            commands::get_catalog_stats,
+            commands::update_pkg_fields,
+            commands::increment_votes,
+            commands::find_orphaned_keys,
+            commands::get_packages_occurences_in_deps,
+            commands::get_connection_profiles,
+            commands::reconnect,
+            commands::run_benchmark,
+            commands::compare_operation,
+            commands::import_dataset,
+            commands::generate_dataset,
+            commands::list_runs,
+            commands::delete_runs,
+            commands::compare_runs,
+            commands::export_runs
         ])
-        .run(tauri::generate_context!())
+        .run(context)
         .expect("error while running tauri application");
 }
@@ -6,7 +6,7 @@ use std::{
 };
 use thiserror::Error;
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct PackageData {
     pub basic: BasicPackageData,
     pub additional: AdditionalPackageData,
@@ -71,7 +71,7 @@ fn get_obligatory_field(
         .ok_or(ModelError::MissingSourceData { field: key })
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct BasicPackageData {
     pub name: String,
     pub version: String,
@@ -150,7 +150,53 @@ impl TryFrom<Vec<String>> for BasicPackageData {
     }
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+/// Partial update of `BasicPackageData`, fields left as `None` keep their stored value.
+/// The name identifies the package and cannot be changed.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct BasicPackagePatch {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub version: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub path_to_additional_data: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub votes: Option<i32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub popularity: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub maintainer: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub last_updated: Option<String>,
+}
+
+impl BasicPackagePatch {
+    pub fn ensure_not_empty(&self) -> Result<(), ModelError> {
+        if *self == Self::default() {
+            return Err(ModelError::EmptyPatch);
+        }
+        Ok(())
+    }
+
+    pub fn apply(&self, basic: &mut BasicPackageData) {
+        fn set<T: Clone>(target: &mut T, value: &Option<T>) {
+            if let Some(value) = value {
+                *target = value.clone();
+            }
+        }
+
+        set(&mut basic.version, &self.version);
+        set(&mut basic.path_to_additional_data, &self.path_to_additional_data);
+        set(&mut basic.votes, &self.votes);
+        set(&mut basic.popularity, &self.popularity);
+        set(&mut basic.description, &self.description);
+        set(&mut basic.maintainer, &self.maintainer);
+        set(&mut basic.last_updated, &self.last_updated);
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct AdditionalPackageData {
     pub git_clone_url: String,
     pub keywords: Option<String>,
@@ -197,13 +243,60 @@ impl TryFrom<HashMap<String, String>> for AdditionalPackageData {
     }
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct PackageDependency {
     pub group: String,
     pub packages: Vec<String>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+/// Transitive dependencies of a package, as resolved by `DbActions::resolve_dependency_tree`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DependencyTree {
+    pub root: String,
+    // Every package reachable from the root which is in the database, sorted by name
+    pub dependencies: Vec<String>,
+    // Referenced packages which are not in the database, sorted by name
+    pub missing: Vec<String>,
+    // Dependencies come before the packages depending on them, the root is last
+    pub install_order: Vec<String>,
+    // Cycles met while walking the graph, each one starting with its smallest name
+    pub cycles: Vec<Vec<String>>,
+}
+
+/// Aggregates over the whole catalog, as computed by `DbActions::get_catalog_stats`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CatalogStats {
+    pub pkg_count: u64,
+    pub total_votes: i64,
+    pub average_votes: f64,
+    pub popularity_histogram: Vec<HistogramBucket>,
+    // Most common first, ties ordered by name; packages without a license are not counted
+    pub top_licenses: Vec<NamedCount>,
+    pub top_maintainers: Vec<NamedCount>,
+    // Number of packages having a given number of comments, ascending by the number of comments
+    pub comment_counts: Vec<CommentCount>,
+}
+
+/// Packages with popularity from `min` up to the `min` of the next bucket, the last bucket has no upper bound.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct HistogramBucket {
+    pub min: f32,
+    pub count: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct NamedCount {
+    pub name: String,
+    pub count: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CommentCount {
+    pub comments: u64,
+    pub pkgs: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Comment {
     pub header: String,
     pub content: String,
@@ -231,4 +324,6 @@ pub enum ModelError {
         field: &'static str,
         source: anyhow::Error,
     },
+    #[error("Patch does not change any field")]
+    EmptyPatch,
 }
@@ -51,6 +51,7 @@ export class DbTestComponent {
   packagesOccurencesForm = new FormGroup({
     targetDb: new FormControl(Db.SurrealDb, [Validators.required]),
     pkgNames: new FormControl('rust,go,python', [Validators.required]),
+    groups: new FormControl(''),
   })
 
   constructor (private dbQueryService: DbQueryService) {}
@@ -116,9 +117,11 @@ export class DbTestComponent {
     this.packagesOccurencesResult = undefined;
     let data = this.packagesOccurencesForm.value;
     let pkgNames = (data.pkgNames as string).split(',');
+    let groups = (data.groups as string).split(',').filter(group => group.length > 0);
     this.dbQueryService.getPackagesOccurencesInDeps(
         data.targetDb as Db,
-        pkgNames as string[]
+        pkgNames as string[],
+        groups
       )
       .catch(err => console.error(err))
       .then(response => this.packagesOccurencesResult = response)
@@ -16,6 +16,16 @@ export interface BasicPackageData {
     last_updated: string,
 }
 
+export interface BasicPackagePatch {
+    version?: string,
+    path_to_additional_data?: string,
+    votes?: number,
+    popularity?: number,
+    description?: string,
+    maintainer?: string,
+    last_updated?: string,
+}
+
 export interface AdditionalPackageData {
     git_clone_url: string,
     keywords: string | null,
@@ -31,6 +41,39 @@ export interface PackageDependency {
     packages: string[],
 }
 
+export interface DependencyTree {
+    root: string,
+    dependencies: string[],
+    missing: string[],
+    install_order: string[],
+    cycles: string[][],
+}
+
+export interface CatalogStats {
+    pkg_count: number,
+    total_votes: number,
+    average_votes: number,
+    popularity_histogram: HistogramBucket[],
+    top_licenses: NamedCount[],
+    top_maintainers: NamedCount[],
+    comment_counts: CommentCount[],
+}
+
+export interface HistogramBucket {
+    min: number,
+    count: number,
+}
+
+export interface NamedCount {
+    name: string,
+    count: number,
+}
+
+export interface CommentCount {
+    comments: number,
+    pkgs: number,
+}
+
 export interface Comment {
      header: string,
      content: string,
@@ -1,6 +1,18 @@
+import { PackageData, BasicPackagePatch } from './package';
+
 export interface QueryResult<T> {
     result: T
     duration: Duration
+    timings: Timings
+}
+
+export interface Timings {
+    connect: Duration,
+    round_trips: Duration,
+    server: Duration | null,
+    deserialization: Duration,
+    processing: Duration,
+    round_trip_count: number
 }
 
 export interface Duration {
@@ -10,11 +22,160 @@ export interface Duration {
 
 export interface QueryCommand {
     target_db: Db,
-    query: string
+    query: string,
+    profile?: string
 }
 
 export enum Db {
     SurrealDb = "SurrealDb",
     Redis = "Redis",
-    Skytable = "Skytable"
+    Skytable = "Skytable",
+    Sqlite = "Sqlite",
+    InMemory = "InMemory"
+}
+
+export type Operation =
+    { name: 'run_custom_query', args: { query: string } } |
+    { name: 'sort_pkgs_by_field_with_limit', args: { field: string, limit_start: number, limit_end: number } } |
+    { name: 'get_most_voted_pkgs', args: { number: number } } |
+    { name: 'insert_pkg', args: { pkg: PackageData } } |
+    { name: 'insert_pkgs', args: { pkgs: PackageData[] } } |
+    { name: 'get_pkg', args: { name: string } } |
+    { name: 'remove_comments', args: { pkg_name: string } } |
+    { name: 'delete_pkg', args: { pkg_name: string } } |
+    { name: 'get_reverse_dependencies', args: { pkg_name: string, groups: string[] } } |
+    { name: 'resolve_dependency_tree', args: { pkg_name: string, groups: string[], max_depth: number | null } } |
+    { name: 'search_pkgs', args: { query: string, limit: number } } |
+    { name: 'get_pkgs_by_maintainer', args: { maintainer: string } } |
+    { name: 'get_pkgs_by_submitter', args: { submitter: string } } |
+    { name: 'get_catalog_stats' } |
+    { name: 'update_pkg_fields', args: { pkg_name: string, patch: BasicPackagePatch } } |
+    { name: 'increment_votes', args: { pkg_name: string, delta: number } } |
+    { name: 'get_packages_occurences_in_deps', args: { pkg_names: string[], groups?: string[] } } |
+    { name: 'find_orphaned_keys', args: { remove: boolean } }
+
+export interface BenchmarkStats {
+    min: Duration,
+    max: Duration,
+    mean: Duration,
+    median: Duration,
+    p95: Duration,
+    p99: Duration,
+    stddev: Duration,
+    samples: Duration[]
+}
+
+export interface BackendOutcome {
+    duration: Duration | null,
+    timings: Timings | null,
+    result: any,
+    error: string | null
+}
+
+export interface Comparison {
+    outcomes: Record<Db, BackendOutcome>,
+    consistent: boolean | null,
+    mismatched: Db[]
+}
+
+// The connection profile a run was made with, the password is never recorded
+export interface ConnectionSettings {
+    host: string,
+    port: number,
+    username: string | null,
+    password: null,
+    namespace: string | null,
+    database: string | null,
+    tls: boolean,
+    redis?: { sort_strategy: 'Sort' | 'SortedSet' }
+}
+
+export interface HostInfo {
+    os: string,
+    arch: string,
+    hostname: string | null,
+    cpus: number
+}
+
+export type Measurement =
+    { kind: 'single', duration: Duration, timings: Timings | null } |
+    { kind: 'benchmark', iterations: number, warmup: number, stats: BenchmarkStats }
+
+export interface Run {
+    id: number,
+    recorded_at: number,
+    db: Db,
+    profile: string,
+    settings: ConnectionSettings,
+    operation: Operation['name'],
+    // Inserts only keep the number of packages and the first and last name
+    arguments: any,
+    dataset: string | null,
+    app_version: string,
+    host: HostInfo,
+    measurement: Measurement
+}
+
+export interface RunFilter {
+    db?: Db,
+    profile?: string,
+    operation?: Operation['name'],
+    dataset?: string,
+    app_version?: string,
+    since?: number,
+    until?: number,
+    limit?: number
+}
+
+export interface RunDelta {
+    run: Run,
+    duration: Duration,
+    change: number
+}
+
+export interface RunComparison {
+    baseline: number,
+    runs: RunDelta[],
+    same_operation: boolean,
+    same_dataset: boolean,
+    same_settings: boolean
+}
+
+export type ExportFormat = 'csv' | 'json' | 'markdown' | 'html'
+
+export interface ImportProgress {
+    db: Db,
+    processed: number,
+    failed: number,
+    total: number
+}
+
+export interface ImportReport {
+    db: Db,
+    imported: number,
+    failed: number,
+    load_time: Duration,
+    elapsed: Duration,
+    errors: string[]
+}
+
+export type Distribution =
+    { kind: 'constant', value: number } |
+    { kind: 'uniform', min: number, max: number } |
+    { kind: 'exponential', mean: number } |
+    { kind: 'pareto', scale: number, shape: number }
+
+export interface DependencyGroupConfig {
+    group: string,
+    probability: number,
+    fan_out: Distribution
+}
+
+export interface GeneratorConfig {
+    seed: number,
+    count: number,
+    votes: Distribution,
+    popularity: Distribution,
+    comments: Distribution,
+    dependency_groups: DependencyGroupConfig[]
 }
@@ -1,64 +1,192 @@
 import { PackageData } from './../model/package';
-import { QueryResult, Db, QueryCommand, Duration } from './../model/query';
+import { QueryResult, Db, QueryCommand, Duration, Operation, BenchmarkStats, Comparison, ImportProgress, ImportReport, GeneratorConfig, Run, RunFilter, RunComparison, ExportFormat } from './../model/query';
 import { Injectable } from "@angular/core";
 import { invoke } from '@tauri-apps/api/tauri';
-import { BasicPackageData } from '../model/package';
+import { listen } from '@tauri-apps/api/event';
+import { save } from '@tauri-apps/api/dialog';
+import { BasicPackageData, BasicPackagePatch, CatalogStats, DependencyTree } from '../model/package';
 
 @Injectable({
     providedIn: 'root',
 })
 export class DbQueryService {
     
-    public runQuery(query: string, targetDb: Db): Promise<QueryResult<string>> {
+    public runQuery(query: string, targetDb: Db, profile?: string): Promise<QueryResult<string>> {
         let queryCommand: QueryCommand = {
             query: query,
-            target_db: targetDb
+            target_db: targetDb,
+            profile: profile
         }
         return invoke<QueryResult<string>>('run_query', { 'queryCommand': queryCommand })
     }
 
-    public getQueryTime(query: string, targetDb: Db): Promise<Duration> {
+    public getQueryTime(query: string, targetDb: Db, profile?: string): Promise<Duration> {
         let queryCommand: QueryCommand = {
             query: query,
-            target_db: targetDb
+            target_db: targetDb,
+            profile: profile
         }
         return invoke<Duration>('get_query_time', { 'queryCommand': queryCommand })
     }
 
-    public sortPkgsByFieldWithLimit(targetDb: Db, field: string, limitStart: number, limitEnd: number) {
+    public sortPkgsByFieldWithLimit(targetDb: Db, field: string, limitStart: number, limitEnd: number, profile?: string) {
         return invoke<QueryResult<string[]>>('sort_pkgs_by_field_with_limit', 
-            { 'targetDb': targetDb, 'field': field, 'limitStart': limitStart, 'limitEnd': limitEnd}
+            { 'targetDb': targetDb, 'profile': profile, 'field': field, 'limitStart': limitStart, 'limitEnd': limitEnd}
         )
     }
 
-    public getMostVotedPackages(targetDb: Db, limit: number) {
+    public getMostVotedPackages(targetDb: Db, limit: number, profile?: string) {
         return invoke<QueryResult<BasicPackageData[]>>('get_most_voted_pkgs', 
-            { 'targetDb': targetDb, 'number': limit }
+            { 'targetDb': targetDb, 'profile': profile, 'number': limit }
         )
     }
 
-    public insertPkg(targetDb: Db, pkg: PackageData) {
+    public insertPkg(targetDb: Db, pkg: PackageData, profile?: string) {
         return invoke<QueryResult<void>>('insert_pkg', 
-            { 'targetDb': targetDb, 'pkg': pkg }
+            { 'targetDb': targetDb, 'profile': profile, 'pkg': pkg }
         )
     }
 
-    public getPkg(targetDb: Db, name: string) {
+    public insertPkgs(targetDb: Db, pkgs: PackageData[], profile?: string) {
+        return invoke<QueryResult<void>>('insert_pkgs', 
+            { 'targetDb': targetDb, 'profile': profile, 'pkgs': pkgs }
+        )
+    }
+
+    public getPkg(targetDb: Db, name: string, profile?: string) {
         return invoke<QueryResult<PackageData>>('get_pkg', 
-            { 'targetDb': targetDb, 'name': name }
+            { 'targetDb': targetDb, 'profile': profile, 'name': name }
         )
     }
 
-    public removeComments(targetDb: Db, pkgName: string) {
+    public removeComments(targetDb: Db, pkgName: string, profile?: string) {
         return invoke<QueryResult<void>>('remove_comments', 
-            { 'targetDb': targetDb, 'pkgName': pkgName }
+            { 'targetDb': targetDb, 'profile': profile, 'pkgName': pkgName }
+        )
+    }
+
+    public deletePkg(targetDb: Db, pkgName: string, profile?: string) {
+        return invoke<QueryResult<void>>('delete_pkg', 
+            { 'targetDb': targetDb, 'profile': profile, 'pkgName': pkgName }
+        )
+    }
+
+    public getReverseDependencies(targetDb: Db, pkgName: string, groups?: string[], profile?: string) {
+        return invoke<QueryResult<string[]>>('get_reverse_dependencies', 
+            { 'targetDb': targetDb, 'profile': profile, 'pkgName': pkgName, 'groups': groups }
+        )
+    }
+
+    public resolveDependencyTree(targetDb: Db, pkgName: string, groups?: string[], maxDepth?: number, profile?: string) {
+        return invoke<QueryResult<DependencyTree>>('resolve_dependency_tree', 
+            { 'targetDb': targetDb, 'profile': profile, 'pkgName': pkgName, 'groups': groups, 'maxDepth': maxDepth }
+        )
+    }
+
+    public searchPkgs(targetDb: Db, query: string, limit: number, profile?: string) {
+        return invoke<QueryResult<string[]>>('search_pkgs', 
+            { 'targetDb': targetDb, 'profile': profile, 'query': query, 'limit': limit }
         )
     }
 
-    public getPackagesOccurencesInDeps(targetDb: Db, pkgNames: string[]) {
+    public getPkgsByMaintainer(targetDb: Db, maintainer: string, profile?: string) {
+        return invoke<QueryResult<string[]>>('get_pkgs_by_maintainer', 
+            { 'targetDb': targetDb, 'profile': profile, 'maintainer': maintainer }
+        )
+    }
+
+    public getPkgsBySubmitter(targetDb: Db, submitter: string, profile?: string) {
+        return invoke<QueryResult<string[]>>('get_pkgs_by_submitter', 
+            { 'targetDb': targetDb, 'profile': profile, 'submitter': submitter }
+        )
+    }
+
+    public getCatalogStats(targetDb: Db, profile?: string) {
+        return invoke<QueryResult<CatalogStats>>('get_catalog_stats', 
+            { 'targetDb': targetDb, 'profile': profile }
+        )
+    }
+
+    public updatePkgFields(targetDb: Db, pkgName: string, patch: BasicPackagePatch, profile?: string) {
+        return invoke<QueryResult<void>>('update_pkg_fields', 
+            { 'targetDb': targetDb, 'profile': profile, 'pkgName': pkgName, 'patch': patch }
+        )
+    }
+
+    public incrementVotes(targetDb: Db, pkgName: string, delta: number, profile?: string) {
+        return invoke<QueryResult<number>>('increment_votes', 
+            { 'targetDb': targetDb, 'profile': profile, 'pkgName': pkgName, 'delta': delta }
+        )
+    }
+
+    public findOrphanedKeys(targetDb: Db, remove: boolean, profile?: string) {
+        return invoke<QueryResult<string[]>>('find_orphaned_keys', 
+            { 'targetDb': targetDb, 'profile': profile, 'remove': remove }
+        )
+    }
+
+    public getPackagesOccurencesInDeps(targetDb: Db, pkgNames: string[], groups?: string[], profile?: string) {
         return invoke<QueryResult<Map<string, number>>>('get_packages_occurences_in_deps', 
-            { 'targetDb': targetDb, 'pkgNames': pkgNames }
+            { 'targetDb': targetDb, 'profile': profile, 'pkgNames': pkgNames, 'groups': groups }
+        )
+    }
+
+    public getConnectionProfiles() {
+        return invoke<Record<Db, string[]>>('get_connection_profiles')
+    }
+
+    public reconnect(targetDb: Db, profile?: string) {
+        return invoke<Duration>('reconnect', { 'targetDb': targetDb, 'profile': profile })
+    }
+
+    public runBenchmark(targetDbs: Db[], operation: Operation, iterations: number, warmup: number, profile?: string) {
+        return invoke<Record<Db, BenchmarkStats>>('run_benchmark',
+            { 'targetDbs': targetDbs, 'profile': profile, 'operation': operation, 'iterations': iterations, 'warmup': warmup }
         )
     }
 
+    public compareOperation(operation: Operation, profile?: string) {
+        return invoke<Comparison>('compare_operation', { 'profile': profile, 'operation': operation })
+    }
+
+    public importDataset(path: string, targetDbs?: Db[], profile?: string, batchSize?: number) {
+        return invoke<ImportReport[]>('import_dataset', { 'path': path, 'targetDbs': targetDbs, 'profile': profile, 'batchSize': batchSize })
+    }
+
+    public onImportProgress(handler: (progress: ImportProgress) => void) {
+        return listen<ImportProgress>('import-progress', event => handler(event.payload))
+    }
+
+    public generateDataset(config: GeneratorConfig, path: string) {
+        return invoke<number>('generate_dataset', { 'config': config, 'path': path })
+    }
+
+    public listRuns(filter?: RunFilter) {
+        return invoke<Run[]>('list_runs', { 'filter': filter })
+    }
+
+    public deleteRuns(ids: number[]) {
+        return invoke<number>('delete_runs', { 'ids': ids })
+    }
+
+    public compareRuns(ids: number[]) {
+        return invoke<RunComparison>('compare_runs', { 'ids': ids })
+    }
+
+    // Asks for the target file first, resolves to null when the dialog was cancelled
+    public async exportRuns(selection: { ids?: number[], filter?: RunFilter }, format?: ExportFormat): Promise<number | null> {
+        const path = await save({
+            filters: [
+                { name: 'CSV', extensions: ['csv'] },
+                { name: 'JSON', extensions: ['json'] },
+                { name: 'Markdown report', extensions: ['md'] },
+                { name: 'HTML report', extensions: ['html'] }
+            ]
+        })
+        if (path === null) {
+            return null
+        }
+        return invoke<number>('export_runs', { 'path': path, 'ids': selection.ids, 'filter': selection.filter, 'format': format })
+    }
+
 }
\ No newline at end of file